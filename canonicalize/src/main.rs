@@ -1,6 +1,66 @@
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CanonicalizeOptions, ClipRegion, Command, CommandReader};
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(help = "HPGL file to canonicalize, or '-' to read from stdin")]
+    file: PathBuf,
+    #[structopt(
+        long,
+        help = "Break dashed and dotted lines (set with LT) up into separate strokes"
+    )]
+    expand_line_types: bool,
+    #[structopt(
+        long,
+        help = "Draw labels (LB and UC) as strokes, using the plotter's character set 0"
+    )]
+    expand_labels: bool,
+    #[structopt(
+        long,
+        help = "Only draw what's inside of this region, given as the lower left and upper right \
+            corners of a rectangle, or the corners of a polygon, like 0,0,1000,0,500,800"
+    )]
+    clip: Option<ClipRegion>,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
+}
+
 fn main() {
-    let file = std::fs::read_to_string(std::env::args().collect::<Vec<String>>()[1].clone()).unwrap();
-    let cmds = hpgl::parse_commands(file).unwrap();
-    let canonical_cmds = hpgl::canonicalize(cmds);
-    println!("{}", hpgl::canonical_commands_to_string(canonical_cmds));
+    let args = Args::from_args();
+    let path = args.file;
+    let reader = CommandReader::open(&path).unwrap_or_else(|e| {
+        eprintln!("error: couldn't open {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    let mut had_errors = false;
+    let cmds: Vec<Command> = reader
+        .filter_map(|cmd| {
+            cmd.map_err(|e| {
+                eprintln!("{}", e.diagnostic(&path));
+                had_errors = true;
+            })
+            .ok()
+        })
+        .collect();
+    if had_errors {
+        std::process::exit(1);
+    }
+    let options = CanonicalizeOptions {
+        expand_line_types: args.expand_line_types,
+        expand_labels: args.expand_labels,
+        hard_clip_limits: Some(args.model.hard_clip_limits),
+        clip_region: args.clip,
+    };
+    let canonical_cmds = hpgl::canonicalize_with_options(cmds, &options).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    let out = hpgl::canonical_commands_to_string(canonical_cmds).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    println!("{}", out);
 }
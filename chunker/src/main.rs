@@ -14,11 +14,15 @@
 
 extern crate serialport;
 
+use std::cell::RefCell;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Error, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Error, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::{Instant, Duration};
 
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CommandReader, ParseErrorKind, StatsCollector};
 use serialport::prelude::*;
 
 use structopt::StructOpt;
@@ -26,15 +30,18 @@ use structopt::StructOpt;
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 struct Args {
+    #[structopt(help = "HPGL file to plot, or '-' to read from stdin")]
     hpgl_file: PathBuf,
     #[structopt(
         help = "serial device to use, such as /dev/ttyUSB0. attempts to autodetect by default."
     )]
     serial_device: Option<PathBuf>,
-    #[structopt(short = "b", default_value = "60")]
-    buffer_size: usize,
-    #[structopt(long = "baud", default_value = "9600")]
-    baud_rate: u32,
+    #[structopt(short = "b", help = "the plotter's buffer size, in bytes. defaults to the model's.")]
+    buffer_size: Option<usize>,
+    #[structopt(long = "baud", help = "defaults to the model's usual baud rate")]
+    baud_rate: Option<u32>,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
     #[structopt(
         long = "timeout",
         default_value = "30000",
@@ -43,18 +50,81 @@ struct Args {
     timeout: u64,
 }
 
-fn print_progress(percent: f64) {
-    let num_dots = (percent * 80.0) as usize;
-    println!(
-        "\x1B[F{:3}% [{}{}]",
-        (percent * 100.0) as usize,
-        "♥".repeat(num_dots),
-        " ".repeat(80 - num_dots)
-    );
+fn print_progress(bytes_sent: u64, total_bytes: Option<u64>) {
+    match total_bytes {
+        Some(total) if total > 0 => {
+            let percent = (bytes_sent as f64 / total as f64).min(1.0);
+            let num_dots = (percent * 80.0) as usize;
+            println!(
+                "\x1B[F{:3}% [{}{}]",
+                (percent * 100.0) as usize,
+                "♥".repeat(num_dots),
+                " ".repeat(80 - num_dots)
+            );
+        }
+        // we can't know how much of a pipe is left, so just show how far we've gotten
+        _ => println!("\x1B[F{} bytes sent", bytes_sent),
+    }
+}
+
+/// Opens the input for reading, along with its length if it's known ahead of time.
+fn open_input(path: &Path) -> Result<(Box<dyn BufRead>, Option<u64>), Error> {
+    if path == Path::new("-") {
+        Ok((Box::new(BufReader::new(io::stdin())), None))
+    } else {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok((Box::new(BufReader::new(file)), Some(len)))
+    }
+}
+
+/// Passes a reader through, keeping a copy of everything that's read from it, so that commands
+/// can be sent to the plotter exactly as they were written.
+struct Recorder<R> {
+    inner: R,
+    recorded: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<R: BufRead> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.recorded.borrow_mut().extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Recorder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // whatever's being consumed is already in the buffer, so this doesn't read anything
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.recorded.borrow_mut().extend_from_slice(&buf[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Estimates how long the file will take to plot, in seconds. Pipes can only be read once, so
+/// there's no estimate for them.
+fn estimate_time(path: &Path, model: &Model) -> Option<f64> {
+    if path == Path::new("-") {
+        return None;
+    }
+    let mut collector = StatsCollector::new(model.clone());
+    // anything that the plotter can't understand won't take it any time
+    for cmd in CommandReader::open(path).ok()?.flatten() {
+        collector.execute(cmd).ok()?;
+    }
+    Some(collector.stats().time)
 }
 
 fn main() -> Result<(), Error> {
     let args = Args::from_args();
+    let buffer_size = args.buffer_size.unwrap_or(args.model.buffer_size);
+    let baud_rate = args.baud_rate.unwrap_or(args.model.baud_rate);
 
     let serial_device = args.serial_device.unwrap_or({
         let devs: Vec<String> = std::fs::read_dir("/dev/")
@@ -72,7 +142,7 @@ fn main() -> Result<(), Error> {
             let dev = PathBuf::from(devs[0].clone());
             println!("autodetected serial device: {:#?}", dev);
             dev
-        } else if devs.len() == 0 {
+        } else if devs.is_empty() {
             println!("couldn't detect serial device! do you have the driver installed?");
             ::std::process::exit(2);
         } else {
@@ -85,7 +155,7 @@ fn main() -> Result<(), Error> {
     });
 
     let s = SerialPortSettings {
-        baud_rate: args.baud_rate,
+        baud_rate,
         data_bits: DataBits::Eight,
         flow_control: FlowControl::None,
         parity: Parity::None,
@@ -93,37 +163,72 @@ fn main() -> Result<(), Error> {
         timeout: Duration::from_millis(args.timeout),
     };
 
-    let input = File::open(args.hpgl_file)?;
-    let buffered = BufReader::new(input);
-    let mut cmds: Vec<Vec<u8>> = vec![];
-    for cmd in buffered.lines() {
-        cmds.push(cmd?.as_bytes().to_vec());
+    let (input, total_bytes) = open_input(&args.hpgl_file)?;
+    if let Some(time) = estimate_time(&args.hpgl_file, &args.model) {
+        println!("this should take about {} minutes.", (time / 60.0).ceil());
     }
 
-    println!("");
+    println!();
     let start_time = Instant::now();
     match serialport::open_with_settings(&serial_device, &s) {
         Ok(mut port) => {
             let mut next_cmd = vec![];
-            for (i, cmd) in cmds.iter().enumerate() {
-                if next_cmd.len() + cmd.len() < args.buffer_size - 3 {
-                    next_cmd.append(&mut cmd.clone());
+            let mut bytes_read = 0;
+            let recorded = Rc::new(RefCell::new(vec![]));
+            let reader = CommandReader::new(Recorder {
+                inner: input,
+                recorded: Rc::clone(&recorded),
+            });
+            // how far into the input the start of `recorded` is
+            let mut recorded_offset = 0;
+            // commands are read one at a time, so that arbitrarily large files and pipes can be
+            // plotted without reading them into memory first. each one is sent exactly as it was
+            // written, since splitting on `;` would break up labels
+            for cmd in reader.spanned() {
+                let (start, end) = match cmd {
+                    Ok((_, span)) => (span.offset, span.offset + span.len),
+                    Err(e) => {
+                        if let ParseErrorKind::Io(kind) = e.kind {
+                            return Err(Error::new(kind, e.diagnostic(&args.hpgl_file)));
+                        }
+                        // the plotter might understand it even if we don't
+                        println!("{}", e.diagnostic(&args.hpgl_file));
+                        println!("sending it anyway");
+                        println!();
+                        let recorded = recorded.borrow();
+                        let separators = recorded
+                            .iter()
+                            .take_while(|b| **b == b';' || b.is_ascii_whitespace())
+                            .count();
+                        (recorded_offset + separators, recorded_offset + recorded.len())
+                    }
+                };
+                let mut cmd =
+                    recorded.borrow()[start - recorded_offset..end - recorded_offset].to_vec();
+                recorded.borrow_mut().drain(..end - recorded_offset);
+                recorded_offset = end;
+                bytes_read = end as u64;
+                if cmd.last() != Some(&b';') {
+                    cmd.push(b';');
+                }
+                if next_cmd.len() + cmd.len() < buffer_size.saturating_sub(3) {
+                    next_cmd.append(&mut cmd);
                 } else {
-                    port.write(&next_cmd)?;
-                    port.write(b"OA;")?;
-                    print_progress(i as f64 / cmds.len() as f64);
+                    port.write_all(&next_cmd)?;
+                    port.write_all(b"OA;")?;
+                    print_progress(bytes_read, total_bytes);
                     let mut c = 0;
                     while c != 13 {
                         let mut v = vec![0];
-                        port.read(v.as_mut_slice())?;
+                        port.read_exact(v.as_mut_slice())?;
                         c = v[0];
                     }
                     port.clear(ClearBuffer::All)?;
-                    next_cmd = cmd.to_vec();
+                    next_cmd = cmd;
                 }
             }
-            port.write(&next_cmd)?;
-            print_progress(1.0);
+            port.write_all(&next_cmd)?;
+            print_progress(bytes_read, total_bytes);
         }
         Err(e) => {
             println!("Error opening serial port {:#?}: {}", serial_device, e);
@@ -0,0 +1,221 @@
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::transform::{self, Transform};
+use hpgl::{CanonicalizeOptions, Command, CommandReader, Style};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+/// A comma separated list of numbers.
+#[derive(Debug)]
+struct Numbers(Vec<f64>);
+
+impl FromStr for Numbers {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|n| {
+                n.trim()
+                    .parse()
+                    .map_err(|_| format!("'{}' isn't a number", n))
+            })
+            .collect::<Result<_, _>>()
+            .map(Numbers)
+    }
+}
+
+impl Numbers {
+    fn expect(&self, counts: &[usize], flag: &str) -> &[f64] {
+        if !counts.contains(&self.0.len()) {
+            eprintln!(
+                "error: --{} takes {} numbers, but was given {}",
+                flag,
+                counts
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                self.0.len()
+            );
+            std::process::exit(1);
+        }
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+enum Axis {
+    X,
+    Y,
+}
+
+impl FromStr for Axis {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            _ => Err("Could not parse axis".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "hpgl-transform",
+    about = "Moves, scales, rotates and mirrors HPGL files.",
+    after_help = "The transforms are applied in the order they're listed here: --matrix, \
+        --mirror, --scale, --rotate, --translate, and then --fit or --center."
+)]
+struct Args {
+    #[structopt(help = "HPGL file to transform, or '-' to read from stdin")]
+    file: PathBuf,
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Any affine transform, as 'a,b,c,d,e,f', so that (x, y) goes to \
+            (a x + b y + c, d x + e y + f)"
+    )]
+    matrix: Option<Numbers>,
+    #[structopt(
+        long,
+        help = "Mirror across the x axis (y goes to -y) or y axis. Options: 'x', 'y'"
+    )]
+    mirror: Option<Axis>,
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Scale about the origin, as 's' or 'sx,sy'"
+    )]
+    scale: Option<Numbers>,
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Rotate counterclockwise about the origin, in degrees"
+    )]
+    rotate: Option<f64>,
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Move by 'dx,dy', in plotter units"
+    )]
+    translate: Option<Numbers>,
+    #[structopt(
+        long,
+        help = "Scale what's drawn to be as large as it can be on the page, and center it"
+    )]
+    fit: bool,
+    #[structopt(long, help = "Center what's drawn on the page")]
+    center: bool,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "How far from the edges of the page --fit keeps things, in plotter units"
+    )]
+    margin: i32,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
+    #[structopt(
+        long,
+        help = "Write relative movements (PR) rather than absolute ones, as if the pen started at \
+            the origin, so that the output can be drawn from wherever the pen is"
+    )]
+    relative: bool,
+    #[structopt(
+        long,
+        help = "Break dashed and dotted lines (set with LT) up into separate strokes"
+    )]
+    expand_line_types: bool,
+    #[structopt(
+        long,
+        help = "Draw labels (LB and UC) as strokes, so that they're transformed along with \
+            everything else"
+    )]
+    expand_labels: bool,
+}
+
+fn main() {
+    let args = Args::from_args();
+    let path = args.file;
+    let reader = CommandReader::open(&path).unwrap_or_else(|e| {
+        eprintln!("error: couldn't open {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    let mut had_errors = false;
+    let cmds: Vec<Command> = reader
+        .filter_map(|cmd| {
+            cmd.map_err(|e| {
+                eprintln!("{}", e.diagnostic(&path));
+                had_errors = true;
+            })
+            .ok()
+        })
+        .collect();
+    if had_errors {
+        std::process::exit(1);
+    }
+    let options = CanonicalizeOptions {
+        expand_line_types: args.expand_line_types,
+        expand_labels: args.expand_labels,
+        hard_clip_limits: None,
+        clip_region: None,
+    };
+    let cmds = hpgl::canonicalize_with_options(cmds, &options).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut t = Transform::identity();
+    if let Some(matrix) = &args.matrix {
+        let m = matrix.expect(&[6], "matrix");
+        t = t.then(&Transform {
+            matrix: [[m[0], m[1], m[2]], [m[3], m[4], m[5]]],
+        });
+    }
+    match args.mirror {
+        Some(Axis::X) => t = t.then(&Transform::mirror_x()),
+        Some(Axis::Y) => t = t.then(&Transform::mirror_y()),
+        None => {}
+    }
+    if let Some(scale) = &args.scale {
+        let (sx, sy) = match scale.expect(&[1, 2], "scale") {
+            [s] => (*s, *s),
+            s => (s[0], s[1]),
+        };
+        t = t.then(&Transform::scale(sx, sy));
+    }
+    if let Some(degrees) = args.rotate {
+        t = t.then(&Transform::rotate(degrees));
+    }
+    if let Some(translate) = &args.translate {
+        let d = translate.expect(&[2], "translate");
+        t = t.then(&Transform::translate(d[0], d[1]));
+    }
+
+    let fail = |e: transform::TransformError| -> ! {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    };
+    let mut cmds = transform::transform(cmds, &t).unwrap_or_else(|e| fail(e));
+    if args.fit || args.center {
+        if let Some(bounds) = transform::drawing_bounds(&cmds) {
+            let page = if args.fit {
+                transform::fit_to_page(bounds, &args.model, args.margin)
+            } else {
+                transform::center_on_page(bounds, &args.model)
+            };
+            cmds = transform::transform(cmds, &page).unwrap_or_else(|e| fail(e));
+        }
+    }
+
+    let cmds: Vec<Command> = if args.relative {
+        transform::to_relative(cmds)
+    } else {
+        cmds.into_iter().map(Command::from).collect()
+    };
+    let out = hpgl::serialize(&cmds, Style::OnePerLine).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    print!("{}", out);
+}
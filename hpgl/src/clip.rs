@@ -0,0 +1,274 @@
+//! Cutting lines down to only the parts inside of an area, like a plotter's hard clip limits, or
+//! any polygon.
+//!
+//! `CanonicalizeOptions::clip_region` does this while canonicalizing, and `Drawing::clip` does
+//! it to a drawing.
+
+use crate::interpret::{clip_to_window, round_point};
+use crate::{Point, Polyline};
+use std::fmt;
+use std::str::FromStr;
+
+/// An area to clip lines to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipRegion {
+    /// A rectangle, given by its lower left and upper right corners.
+    Rectangle(Point, Point),
+    /// A polygon, given by its corners. It's closed automatically, so the last corner doesn't
+    /// need to be the same as the first. Where it crosses over itself, areas that are inside of
+    /// it an odd number of times count as inside.
+    Polygon(Vec<Point>),
+}
+
+impl ClipRegion {
+    pub fn contains(&self, point: Point) -> bool {
+        match self {
+            ClipRegion::Rectangle(a, b) => {
+                (a.x..=b.x).contains(&point.x) && (a.y..=b.y).contains(&point.y)
+            }
+            ClipRegion::Polygon(corners) => {
+                contains(corners, f64::from(point.x), f64::from(point.y))
+            }
+        }
+    }
+
+    /// The parts of the line from `from` to `to` that are inside of the region, in order from
+    /// `from` to `to`. Points where the line crosses the edge of the region are rounded to the
+    /// nearest plotter unit, and a line that only touches the region at a single point is left
+    /// out altogether.
+    pub fn clip_line(&self, from: Point, to: Point) -> Vec<(Point, Point)> {
+        match self {
+            ClipRegion::Rectangle(a, b) => clip_to_window(from, to, (*a, *b))
+                .into_iter()
+                .filter(|(start, end)| start != end || from == to)
+                .collect(),
+            ClipRegion::Polygon(corners) => clip_to_polygon(from, to, corners),
+        }
+    }
+
+    /// Breaks a polyline up into the parts of it that are inside of the region. A single point is
+    /// kept if it's inside.
+    pub fn clip_polyline(&self, polyline: &[Point]) -> Vec<Polyline> {
+        if polyline.len() == 1 {
+            return if self.contains(polyline[0]) {
+                vec![polyline.to_vec()]
+            } else {
+                vec![]
+            };
+        }
+        let mut out: Vec<Polyline> = vec![];
+        let mut current: Option<Polyline> = None;
+        for line in polyline.windows(2) {
+            for (from, to) in self.clip_line(line[0], line[1]) {
+                match &mut current {
+                    Some(current) if current.last() == Some(&from) => current.push(to),
+                    _ => out.extend(current.replace(vec![from, to])),
+                }
+            }
+        }
+        out.extend(current);
+        out
+    }
+}
+
+fn point_at(from: Point, to: Point, t: f64) -> Point {
+    if t <= 0.0 {
+        return from;
+    } else if t >= 1.0 {
+        return to;
+    }
+    let (x, y) = (f64::from(from.x), f64::from(from.y));
+    let (dx, dy) = (f64::from(to.x) - x, f64::from(to.y) - y);
+    // anywhere between two points in range is in range too
+    round_point(x + t * dx, y + t * dy).unwrap()
+}
+
+/// Whether `(x, y)` is inside of a polygon, by counting how many of its edges a line going right
+/// from the point crosses.
+fn contains(corners: &[Point], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        let (ax, ay) = (f64::from(a.x), f64::from(a.y));
+        let (bx, by) = (f64::from(b.x), f64::from(b.y));
+        if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn clip_to_polygon(from: Point, to: Point, corners: &[Point]) -> Vec<(Point, Point)> {
+    let (x, y) = (f64::from(from.x), f64::from(from.y));
+    let (dx, dy) = (f64::from(to.x) - x, f64::from(to.y) - y);
+    // where along the line (from 0 at `from` to 1 at `to`) it crosses each of the edges
+    let mut crossings = vec![0.0, 1.0];
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        let (ax, ay) = (f64::from(a.x), f64::from(a.y));
+        let (ex, ey) = (f64::from(b.x) - ax, f64::from(b.y) - ay);
+        let denominator = dx * ey - dy * ex;
+        if denominator == 0.0 {
+            // parallel, so it either doesn't cross, or runs along the edge, which the checks
+            // below take care of
+            continue;
+        }
+        let t = ((ax - x) * ey - (ay - y) * ex) / denominator;
+        let u = ((ax - x) * dy - (ay - y) * dx) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            crossings.push(t);
+        }
+    }
+    crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    crossings.dedup();
+
+    // each piece between two crossings is either all inside or all outside
+    let mut pieces: Vec<(f64, f64)> = vec![];
+    for pair in crossings.windows(2) {
+        let middle = (pair[0] + pair[1]) / 2.0;
+        if !contains(corners, x + middle * dx, y + middle * dy) {
+            continue;
+        }
+        match pieces.last_mut() {
+            Some(last) if last.1 == pair[0] => last.1 = pair[1],
+            _ => pieces.push((pair[0], pair[1])),
+        }
+    }
+    if from == to && contains(corners, x, y) {
+        return vec![(from, to)];
+    }
+    pieces
+        .into_iter()
+        .map(|(start, end)| (point_at(from, to, start), point_at(from, to, end)))
+        .filter(|(start, end)| start != end)
+        .collect()
+}
+
+impl fmt::Display for ClipRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let corners = match self {
+            ClipRegion::Rectangle(a, b) => vec![*a, *b],
+            ClipRegion::Polygon(corners) => corners.clone(),
+        };
+        let numbers: Vec<String> = corners.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+        write!(f, "{}", numbers.join(","))
+    }
+}
+
+/// Reads a region from a comma separated list of coordinates: two points for the lower left and
+/// upper right corners of a rectangle, or three or more for the corners of a polygon.
+impl FromStr for ClipRegion {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers = s
+            .split(',')
+            .map(|n| {
+                n.trim()
+                    .parse()
+                    .map_err(|_| format!("'{}' isn't a whole number", n.trim()))
+            })
+            .collect::<Result<Vec<i32>, _>>()?;
+        if numbers.len() % 2 != 0 {
+            return Err("the coordinates don't come in pairs".to_string());
+        }
+        let corners: Vec<Point> = numbers
+            .chunks(2)
+            .map(|c| Point { x: c[0], y: c[1] })
+            .collect();
+        match corners.len() {
+            2 if corners[0].x <= corners[1].x && corners[0].y <= corners[1].y => {
+                Ok(ClipRegion::Rectangle(corners[0], corners[1]))
+            }
+            2 => Err("a rectangle's first corner has to be its lower left one".to_string()),
+            n if n >= 3 => Ok(ClipRegion::Polygon(corners)),
+            _ => Err(
+                "a region needs two corners for a rectangle, or at least three for a polygon"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let region = ClipRegion::Rectangle(p(0, 0), p(100, 100));
+        assert_eq!(
+            region.clip_line(p(-50, 50), p(50, 50)),
+            vec![(p(0, 50), p(50, 50))]
+        );
+        assert_eq!(region.clip_line(p(-50, 50), p(-10, 50)), vec![]);
+        assert_eq!(
+            region.clip_polyline(&[p(50, 50), p(150, 50), p(150, 80), p(50, 80), p(50, 90)]),
+            vec![
+                vec![p(50, 50), p(100, 50)],
+                vec![p(100, 80), p(50, 80), p(50, 90)]
+            ]
+        );
+        assert_eq!(region.clip_polyline(&[p(5, 5)]), vec![vec![p(5, 5)]]);
+        assert_eq!(region.clip_polyline(&[p(-5, 5)]), Vec::<Polyline>::new());
+    }
+
+    #[test]
+    fn test_polygon() {
+        // a "U" shape
+        let region = ClipRegion::Polygon(vec![
+            p(0, 0),
+            p(300, 0),
+            p(300, 300),
+            p(200, 300),
+            p(200, 100),
+            p(100, 100),
+            p(100, 300),
+            p(0, 300),
+        ]);
+        assert!(region.contains(p(50, 200)));
+        assert!(!region.contains(p(150, 200)));
+        assert!(region.contains(p(150, 50)));
+        // straight across both arms of the "U"
+        assert_eq!(
+            region.clip_line(p(-100, 200), p(400, 200)),
+            vec![(p(0, 200), p(100, 200)), (p(200, 200), p(300, 200))]
+        );
+        // across the bottom, where it's all inside
+        assert_eq!(
+            region.clip_line(p(-100, 50), p(400, 50)),
+            vec![(p(0, 50), p(300, 50))]
+        );
+        // diagonally, from inside one arm, with a crossing that has to be rounded
+        assert_eq!(
+            region.clip_line(p(50, 250), p(250, 150)),
+            vec![(p(50, 250), p(100, 225)), (p(200, 175), p(250, 150))]
+        );
+        assert_eq!(region.clip_polyline(&[p(150, 150)]), Vec::<Polyline>::new());
+        assert_eq!(
+            region.clip_line(p(50, 50), p(50, 50)),
+            vec![(p(50, 50), p(50, 50))]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "0,0,100,200".parse(),
+            Ok(ClipRegion::Rectangle(p(0, 0), p(100, 200)))
+        );
+        assert_eq!(
+            "0,0, 100,0, 50,50".parse(),
+            Ok(ClipRegion::Polygon(vec![p(0, 0), p(100, 0), p(50, 50)]))
+        );
+        assert!("0,0,100".parse::<ClipRegion>().is_err());
+        assert!("0,0".parse::<ClipRegion>().is_err());
+        assert!("100,100,0,0".parse::<ClipRegion>().is_err());
+        assert!("0,0,a,b".parse::<ClipRegion>().is_err());
+        let region = ClipRegion::Polygon(vec![p(0, 0), p(100, 0), p(50, -50)]);
+        assert_eq!(region.to_string().parse(), Ok(region));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::Point;
+use std::fmt;
+
+/// The default label terminator, used by `LB` until it's changed with `DT`.
+pub const ETX: char = '\x03';
+
+/// The instructions supported by the HP7440A, along with the arc and circle instructions from
+/// the graphics enhancement cartridge (which the HP7475A has built in).
+///
+/// Parameters that the plotter lets you leave off are `Option`s, nested so that only trailing
+/// parameters can be omitted, as in HPGL itself.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    PenUp(Vec<Point>),
+    PenDown(Vec<Point>),
+    PlotAbsolute(Vec<Point>),
+    PlotRelative(Vec<Point>),
+    SelectPen(u8),
+    /// `CI`, with the radius and the chord angle, in degrees.
+    Circle(i32, Option<f64>),
+    /// `AA`, with the center, the angle to sweep through, in degrees counterclockwise, and the
+    /// chord angle.
+    ArcAbsolute(Point, f64, Option<f64>),
+    /// `AR`, like `AA`, but with the center relative to the current position.
+    ArcRelative(Point, f64, Option<f64>),
+    Initialize,
+    /// `DF`, which sets most of the plotter state back to its defaults.
+    Default,
+    /// `IP`, with P1 and optionally P2, in plotter units.
+    InputP1P2(Option<(Point, Option<Point>)>),
+    /// `IW`, with the lower left and upper right corners of the window, in plotter units.
+    InputWindow(Option<(Point, Point)>),
+    /// `SC`, which maps user units onto P1 and P2.
+    Scale(Option<Scaling>),
+    /// `VS`, in cm/s.
+    VelocitySelect(Option<f64>),
+    /// `LT`, with a pattern number and a pattern length, in percent of the diagonal from P1 to P2.
+    LineType(Option<(u8, Option<f64>)>),
+    /// `TL`, with the positive and negative tick lengths, in percent of P2 - P1.
+    TickLength(Option<(f64, Option<f64>)>),
+    XTick,
+    YTick,
+    /// `LB`, with the text of the label, not including the terminator.
+    Label(String),
+    /// `DT`, with the new label terminator - `DT;` goes back to `ETX`.
+    DefineTerminator(Option<char>),
+    /// `CA`, with the character set number.
+    DesignateAlternateSet(Option<u8>),
+    /// `CS`, with the character set number.
+    DesignateStandardSet(Option<u8>),
+    SelectAlternateSet,
+    SelectStandardSet,
+    /// `CP`, with the number of character spaces and lines to move - `CP;` is a carriage return
+    /// and line feed.
+    CharacterPlot(Option<(f64, f64)>),
+    /// `DI`, with the run and rise of the label direction.
+    AbsoluteDirection(Option<(f64, f64)>),
+    /// `DR`, with the run and rise of the label direction, in percent of P2 - P1.
+    RelativeDirection(Option<(f64, f64)>),
+    /// `SI`, with the character width and height, in cm.
+    AbsoluteCharacterSize(Option<(f64, f64)>),
+    /// `SR`, with the character width and height, in percent of P2 - P1.
+    RelativeCharacterSize(Option<(f64, f64)>),
+    /// `SL`, with the tangent of the slant angle.
+    CharacterSlant(Option<f64>),
+    /// `SM`, with the character to draw at each point - `SM;` turns symbol mode off.
+    SymbolMode(Option<char>),
+    /// `UC`, a character drawn from pen movements on a grid.
+    UserDefinedCharacter(Vec<CharacterStroke>),
+    /// `IM`, with the error, service request and parallel poll masks.
+    InputMask(Option<InputMask>),
+    DigitizeClear,
+    DigitizePoint,
+    OutputActualPosition,
+    OutputCommandedPosition,
+    OutputDigitizedPoint,
+    OutputError,
+    OutputFactors,
+    OutputHardClipLimits,
+    OutputIdentification,
+    OutputOptions,
+    OutputP1P2,
+    OutputStatus,
+    OutputWindow,
+}
+
+/// The user unit ranges set by `SC`, mapped onto P1 and P2 respectively.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scaling {
+    pub x_min: i32,
+    pub x_max: i32,
+    pub y_min: i32,
+    pub y_max: i32,
+}
+
+/// The masks set by `IM`. As in HPGL, the parallel poll mask can only be given along with the
+/// service request mask.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputMask {
+    pub error: u8,
+    pub service_request: Option<u8>,
+    pub parallel_poll: Option<u8>,
+}
+
+/// One step of a `UC` character.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CharacterStroke {
+    /// `99` in HPGL.
+    PenDown,
+    /// `-99` in HPGL.
+    PenUp,
+    /// A move relative to the last position, in character grid units.
+    Move(i32, i32),
+}
+
+impl Command {
+    /// The two letter HPGL mnemonic for the command.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Command::PenUp(_) => "PU",
+            Command::PenDown(_) => "PD",
+            Command::PlotAbsolute(_) => "PA",
+            Command::PlotRelative(_) => "PR",
+            Command::SelectPen(_) => "SP",
+            Command::Circle(..) => "CI",
+            Command::ArcAbsolute(..) => "AA",
+            Command::ArcRelative(..) => "AR",
+            Command::Initialize => "IN",
+            Command::Default => "DF",
+            Command::InputP1P2(_) => "IP",
+            Command::InputWindow(_) => "IW",
+            Command::Scale(_) => "SC",
+            Command::VelocitySelect(_) => "VS",
+            Command::LineType(_) => "LT",
+            Command::TickLength(_) => "TL",
+            Command::XTick => "XT",
+            Command::YTick => "YT",
+            Command::Label(_) => "LB",
+            Command::DefineTerminator(_) => "DT",
+            Command::DesignateAlternateSet(_) => "CA",
+            Command::DesignateStandardSet(_) => "CS",
+            Command::SelectAlternateSet => "SA",
+            Command::SelectStandardSet => "SS",
+            Command::CharacterPlot(_) => "CP",
+            Command::AbsoluteDirection(_) => "DI",
+            Command::RelativeDirection(_) => "DR",
+            Command::AbsoluteCharacterSize(_) => "SI",
+            Command::RelativeCharacterSize(_) => "SR",
+            Command::CharacterSlant(_) => "SL",
+            Command::SymbolMode(_) => "SM",
+            Command::UserDefinedCharacter(_) => "UC",
+            Command::InputMask(_) => "IM",
+            Command::DigitizeClear => "DC",
+            Command::DigitizePoint => "DP",
+            Command::OutputActualPosition => "OA",
+            Command::OutputCommandedPosition => "OC",
+            Command::OutputDigitizedPoint => "OD",
+            Command::OutputError => "OE",
+            Command::OutputFactors => "OF",
+            Command::OutputHardClipLimits => "OH",
+            Command::OutputIdentification => "OI",
+            Command::OutputOptions => "OO",
+            Command::OutputP1P2 => "OP",
+            Command::OutputStatus => "OS",
+            Command::OutputWindow => "OW",
+        }
+    }
+}
+
+fn write_points(f: &mut fmt::Formatter, points: &[Point]) -> fmt::Result {
+    for (i, point) in points.iter().enumerate() {
+        if i != 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{},{}", point.x, point.y)?;
+    }
+    Ok(())
+}
+
+/// Writes a command as HPGL, in its most compact form.
+///
+/// Labels are always terminated with `ETX`, since a single command can't know about any `DT`
+/// that came before it.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match self {
+            Command::PenUp(points)
+            | Command::PenDown(points)
+            | Command::PlotAbsolute(points)
+            | Command::PlotRelative(points) => write_points(f, points)?,
+            Command::SelectPen(pen) => write!(f, "{}", pen)?,
+            Command::Circle(radius, chord_angle) => {
+                write!(f, "{}", radius)?;
+                if let Some(chord_angle) = chord_angle {
+                    write!(f, ",{}", chord_angle)?;
+                }
+            }
+            Command::ArcAbsolute(center, angle, chord_angle)
+            | Command::ArcRelative(center, angle, chord_angle) => {
+                write!(f, "{},{},{}", center.x, center.y, angle)?;
+                if let Some(chord_angle) = chord_angle {
+                    write!(f, ",{}", chord_angle)?;
+                }
+            }
+            Command::InputP1P2(Some((p1, p2))) => {
+                write_points(f, &[*p1])?;
+                if let Some(p2) = p2 {
+                    write!(f, ",")?;
+                    write_points(f, &[*p2])?;
+                }
+            }
+            Command::InputWindow(Some((p1, p2))) => write_points(f, &[*p1, *p2])?,
+            Command::Scale(Some(s)) => {
+                write!(f, "{},{},{},{}", s.x_min, s.x_max, s.y_min, s.y_max)?
+            }
+            Command::VelocitySelect(Some(v)) | Command::CharacterSlant(Some(v)) => {
+                write!(f, "{}", v)?
+            }
+            Command::LineType(Some((pattern, length))) => {
+                write!(f, "{}", pattern)?;
+                if let Some(length) = length {
+                    write!(f, ",{}", length)?;
+                }
+            }
+            Command::TickLength(Some((positive, negative))) => {
+                write!(f, "{}", positive)?;
+                if let Some(negative) = negative {
+                    write!(f, ",{}", negative)?;
+                }
+            }
+            Command::Label(text) => return write!(f, "{}{}", text, ETX),
+            Command::DefineTerminator(Some(c)) | Command::SymbolMode(Some(c)) => {
+                write!(f, "{}", c)?
+            }
+            Command::DesignateAlternateSet(Some(set))
+            | Command::DesignateStandardSet(Some(set)) => write!(f, "{}", set)?,
+            Command::CharacterPlot(Some((a, b)))
+            | Command::AbsoluteDirection(Some((a, b)))
+            | Command::RelativeDirection(Some((a, b)))
+            | Command::AbsoluteCharacterSize(Some((a, b)))
+            | Command::RelativeCharacterSize(Some((a, b))) => write!(f, "{},{}", a, b)?,
+            Command::UserDefinedCharacter(strokes) => {
+                for (i, stroke) in strokes.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    match stroke {
+                        CharacterStroke::PenDown => write!(f, "99")?,
+                        CharacterStroke::PenUp => write!(f, "-99")?,
+                        CharacterStroke::Move(x, y) => write!(f, "{},{}", x, y)?,
+                    }
+                }
+            }
+            Command::InputMask(Some(mask)) => {
+                write!(f, "{}", mask.error)?;
+                if let Some(service_request) = mask.service_request {
+                    write!(f, ",{}", service_request)?;
+                    if let Some(parallel_poll) = mask.parallel_poll {
+                        write!(f, ",{}", parallel_poll)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        write!(f, ";")
+    }
+}
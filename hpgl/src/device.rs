@@ -0,0 +1,443 @@
+//! Descriptions of the plotters that HPGL gets drawn on.
+//!
+//! A few models are built in (see `builtin_models`). More can be defined in a TOML file, which
+//! is read from `$HPGL_MODELS` if it's set, or `$XDG_CONFIG_HOME/hpgl/models.toml` (falling back
+//! to `~/.config/hpgl/models.toml`) otherwise. Each table in it is a model, named by its key:
+//!
+//! ```toml
+//! [my7475]
+//! description = "HP7475A, with B size paper"
+//! hard_clip_limits = [0, 0, 16640, 10365]
+//! orientation = "landscape"
+//! pens = 6
+//! buffer_size = 1024
+//! instruction_set = "7475a"
+//! baud_rate = 9600
+//! # these are optional, and default to the HP7440A's
+//! max_velocity = 38.1
+//! travel_velocity = 38.1
+//! pen_dwell = 0.05
+//! pen_change_time = 3.0
+//! ```
+//!
+//! A model in the file with the same name as a built in one replaces it.
+
+use crate::{Command, Point};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The model that tools use when they aren't given one.
+pub const DEFAULT_MODEL: &str = "7440";
+
+/// The help text for the `--model` flag that every tool takes.
+pub const MODEL_HELP: &str = "Plotter model: '7440', '7475a3', '7475a4', or one defined in \
+    $HPGL_MODELS or ~/.config/hpgl/models.toml";
+
+/// Which way the plotter's axes run along the paper.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// The x axis runs along the long edge of the paper.
+    Landscape,
+    /// The x axis runs along the short edge of the paper.
+    Portrait,
+}
+
+/// The instructions a plotter understands.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum InstructionSet {
+    /// The HP7440A, without the graphics enhancement cartridge, which doesn't have the arc and
+    /// circle instructions.
+    #[serde(rename = "7440a")]
+    Hp7440A,
+    /// The HP7475A, which has everything that `Command` can represent. This is also what a
+    /// HP7440A with the graphics enhancement cartridge understands.
+    #[serde(rename = "7475a")]
+    Hp7475A,
+}
+
+impl InstructionSet {
+    /// Whether a plotter with this instruction set understands a command.
+    pub fn supports(self, cmd: &Command) -> bool {
+        match self {
+            InstructionSet::Hp7440A => !matches!(
+                cmd,
+                Command::Circle(..) | Command::ArcAbsolute(..) | Command::ArcRelative(..)
+            ),
+            InstructionSet::Hp7475A => true,
+        }
+    }
+}
+
+/// Everything the tools need to know about a plotter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    /// What the model is called on the command line.
+    pub name: String,
+    pub description: String,
+    /// The lower left and upper right corners of the area the pen can reach, in plotter units.
+    pub hard_clip_limits: (Point, Point),
+    pub orientation: Orientation,
+    /// How many pens the carousel holds.
+    pub pens: u8,
+    /// The size of the plotter's input buffer, in bytes.
+    pub buffer_size: usize,
+    pub instruction_set: InstructionSet,
+    /// The baud rate the plotter's serial port is usually set to.
+    pub baud_rate: u32,
+    /// The fastest the pen can draw, in cm/s, which is also the speed `VS` goes back to by
+    /// default.
+    pub max_velocity: f64,
+    /// How fast the pen moves while it's up, in cm/s.
+    pub travel_velocity: f64,
+    /// Roughly how long it takes to lift or lower the pen, in seconds.
+    pub pen_dwell: f64,
+    /// Roughly how long it takes to put a pen back in the carousel and pick up another one, in
+    /// seconds.
+    pub pen_change_time: f64,
+}
+
+/// The speeds and times that a model in the models file gets if it doesn't give its own, which
+/// are those of the HP7440A.
+const DEFAULT_VELOCITY: f64 = 38.1;
+const DEFAULT_PEN_DWELL: f64 = 0.05;
+const DEFAULT_PEN_CHANGE_TIME: f64 = 3.0;
+
+impl Model {
+    /// Looks up a model by name, in the user's models file and then in the built in models.
+    pub fn find(name: &str) -> Result<Model, ModelError> {
+        let models = models()?;
+        match models.iter().find(|m| m.name == name) {
+            Some(model) => Ok(model.clone()),
+            None => Err(ModelError::Unknown {
+                name: name.to_string(),
+                known: models.into_iter().map(|m| m.name).collect(),
+            }),
+        }
+    }
+
+    /// The width and height of the area the pen can reach, in plotter units.
+    pub fn size(&self) -> (i32, i32) {
+        let (a, b) = self.hard_clip_limits;
+        (b.x.saturating_sub(a.x), b.y.saturating_sub(a.y))
+    }
+
+    /// Whether the pen can reach a point.
+    pub fn contains(&self, point: Point) -> bool {
+        let (a, b) = self.hard_clip_limits;
+        (a.x..=b.x).contains(&point.x) && (a.y..=b.y).contains(&point.y)
+    }
+}
+
+impl FromStr for Model {
+    type Err = ModelError;
+
+    fn from_str(name: &str) -> Result<Model, ModelError> {
+        Model::find(name)
+    }
+}
+
+fn builtin(
+    name: &str,
+    description: &str,
+    upper_right: Point,
+    pens: u8,
+    buffer_size: usize,
+    instruction_set: InstructionSet,
+) -> Model {
+    Model {
+        name: name.to_string(),
+        description: description.to_string(),
+        hard_clip_limits: (Point { x: 0, y: 0 }, upper_right),
+        orientation: Orientation::Landscape,
+        pens,
+        buffer_size,
+        instruction_set,
+        baud_rate: 9600,
+        max_velocity: DEFAULT_VELOCITY,
+        travel_velocity: DEFAULT_VELOCITY,
+        pen_dwell: DEFAULT_PEN_DWELL,
+        pen_change_time: DEFAULT_PEN_CHANGE_TIME,
+    }
+}
+
+/// The models that are always available.
+pub fn builtin_models() -> Vec<Model> {
+    vec![
+        builtin(
+            "7440",
+            "HP7440A ColorPro",
+            Point { x: 10300, y: 7650 },
+            8,
+            60,
+            InstructionSet::Hp7440A,
+        ),
+        builtin(
+            "7475a3",
+            "HP7475A, with the large paper size",
+            Point { x: 16640, y: 10365 },
+            6,
+            1024,
+            InstructionSet::Hp7475A,
+        ),
+        builtin(
+            "7475a4",
+            "HP7475A, with the small paper size",
+            Point { x: 10365, y: 7962 },
+            6,
+            1024,
+            InstructionSet::Hp7475A,
+        ),
+    ]
+}
+
+/// Every model that's available: the ones in the user's models file (if there is one), followed
+/// by the built in ones that they don't replace.
+pub fn models() -> Result<Vec<Model>, ModelError> {
+    let mut models = match models_path() {
+        Some(path) if path.exists() || std::env::var_os("HPGL_MODELS").is_some() => {
+            read_models(&path)?
+        }
+        _ => vec![],
+    };
+    for model in builtin_models() {
+        if !models.iter().any(|m| m.name == model.name) {
+            models.push(model);
+        }
+    }
+    Ok(models)
+}
+
+/// Where the user's models file is, if there's anywhere it could be.
+pub fn models_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("HPGL_MODELS") {
+        return Some(PathBuf::from(path));
+    }
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("hpgl").join("models.toml"))
+}
+
+/// A model as it's written in the models file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDefinition {
+    #[serde(default)]
+    description: String,
+    hard_clip_limits: [i32; 4],
+    orientation: Orientation,
+    pens: u8,
+    buffer_size: usize,
+    instruction_set: InstructionSet,
+    baud_rate: u32,
+    #[serde(default = "default_velocity")]
+    max_velocity: f64,
+    #[serde(default = "default_velocity")]
+    travel_velocity: f64,
+    #[serde(default = "default_pen_dwell")]
+    pen_dwell: f64,
+    #[serde(default = "default_pen_change_time")]
+    pen_change_time: f64,
+}
+
+fn default_velocity() -> f64 {
+    DEFAULT_VELOCITY
+}
+
+fn default_pen_dwell() -> f64 {
+    DEFAULT_PEN_DWELL
+}
+
+fn default_pen_change_time() -> f64 {
+    DEFAULT_PEN_CHANGE_TIME
+}
+
+/// Reads the models defined in a models file.
+pub fn read_models(path: &Path) -> Result<Vec<Model>, ModelError> {
+    let text = std::fs::read_to_string(path).map_err(|error| ModelError::Read {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse_models(&text, path)
+}
+
+fn parse_models(text: &str, path: &Path) -> Result<Vec<Model>, ModelError> {
+    let definitions: BTreeMap<String, ModelDefinition> =
+        toml::from_str(text).map_err(|error| ModelError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    let mut models = vec![];
+    for (name, d) in definitions {
+        let [x_min, y_min, x_max, y_max] = d.hard_clip_limits;
+        let invalid = |reason: &str| ModelError::Invalid {
+            path: path.to_path_buf(),
+            name: name.clone(),
+            reason: reason.to_string(),
+        };
+        if x_min >= x_max || y_min >= y_max {
+            return Err(invalid("the hard clip limits don't cover any area"));
+        }
+        if d.pens == 0 {
+            return Err(invalid("there has to be at least one pen"));
+        }
+        if d.buffer_size == 0 || d.baud_rate == 0 {
+            return Err(invalid("the buffer size and baud rate can't be 0"));
+        }
+        if !(d.max_velocity > 0.0 && d.travel_velocity > 0.0) {
+            return Err(invalid("the velocities have to be more than 0"));
+        }
+        if !(d.pen_dwell >= 0.0 && d.pen_change_time >= 0.0) {
+            return Err(invalid(
+                "the pen dwell and pen change time can't be negative",
+            ));
+        }
+        models.push(Model {
+            name,
+            description: d.description,
+            hard_clip_limits: (Point { x: x_min, y: y_min }, Point { x: x_max, y: y_max }),
+            orientation: d.orientation,
+            pens: d.pens,
+            buffer_size: d.buffer_size,
+            instruction_set: d.instruction_set,
+            baud_rate: d.baud_rate,
+            max_velocity: d.max_velocity,
+            travel_velocity: d.travel_velocity,
+            pen_dwell: d.pen_dwell,
+            pen_change_time: d.pen_change_time,
+        });
+    }
+    Ok(models)
+}
+
+/// Why a model couldn't be found.
+#[derive(Debug)]
+pub enum ModelError {
+    Unknown {
+        name: String,
+        known: Vec<String>,
+    },
+    Read {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        name: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Unknown { name, known } => write!(
+                f,
+                "unknown plotter model '{}' (known models: {})",
+                name,
+                known.join(", ")
+            ),
+            ModelError::Read { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            }
+            ModelError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            ModelError::Invalid { path, name, reason } => {
+                write!(f, "{}: model '{}': {}", path.display(), name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builtin_models() {
+        let models = builtin_models();
+        let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["7440", "7475a3", "7475a4"]);
+        assert!(names.contains(&DEFAULT_MODEL));
+        assert_eq!(models[0].size(), (10300, 7650));
+        assert!(models[0].contains(Point { x: 10300, y: 0 }));
+        assert!(!models[0].contains(Point { x: 10301, y: 0 }));
+        assert!(!models[0]
+            .instruction_set
+            .supports(&Command::Circle(100, None)));
+        assert!(models[1]
+            .instruction_set
+            .supports(&Command::Circle(100, None)));
+    }
+
+    #[test]
+    fn test_parse_models() {
+        let models = parse_models(
+            r#"
+            [big]
+            hard_clip_limits = [-100, 0, 20000, 15000]
+            orientation = "portrait"
+            pens = 1
+            buffer_size = 256
+            instruction_set = "7475a"
+            baud_rate = 2400
+            max_velocity = 10
+            pen_dwell = 0.5
+            "#,
+            Path::new("models.toml"),
+        )
+        .unwrap();
+        assert_eq!(
+            models,
+            vec![Model {
+                name: "big".to_string(),
+                description: String::new(),
+                hard_clip_limits: (Point { x: -100, y: 0 }, Point { x: 20000, y: 15000 }),
+                orientation: Orientation::Portrait,
+                pens: 1,
+                buffer_size: 256,
+                instruction_set: InstructionSet::Hp7475A,
+                baud_rate: 2400,
+                max_velocity: 10.0,
+                travel_velocity: DEFAULT_VELOCITY,
+                pen_dwell: 0.5,
+                pen_change_time: DEFAULT_PEN_CHANGE_TIME,
+            }]
+        );
+
+        let errors = vec![
+            // missing fields
+            "[a]\npens = 1",
+            // unknown fields
+            "[a]\nhard_clip_limits = [0, 0, 1, 1]\norientation = \"portrait\"\npens = 1\n\
+             buffer_size = 1\ninstruction_set = \"7475a\"\nbaud_rate = 1\ncolor = \"red\"",
+            // unknown instruction set
+            "[a]\nhard_clip_limits = [0, 0, 1, 1]\norientation = \"portrait\"\npens = 1\n\
+             buffer_size = 1\ninstruction_set = \"7580\"\nbaud_rate = 1",
+            // empty hard clip limits
+            "[a]\nhard_clip_limits = [0, 0, 0, 1]\norientation = \"portrait\"\npens = 1\n\
+             buffer_size = 1\ninstruction_set = \"7475a\"\nbaud_rate = 1",
+            // no pens
+            "[a]\nhard_clip_limits = [0, 0, 1, 1]\norientation = \"portrait\"\npens = 0\n\
+             buffer_size = 1\ninstruction_set = \"7475a\"\nbaud_rate = 1",
+        ];
+        for text in errors {
+            assert!(
+                parse_models(text, Path::new("models.toml")).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,634 @@
+use crate::{
+    canonicalize_with_options, simplify, CanonicalCommand, CanonicalizeOptions, ClipRegion,
+    Command, InterpretError, Point, DEFAULT_P1, DEFAULT_P2,
+};
+use std::collections::BTreeMap;
+
+/// A pen number, as given to `SP`. Pen `0` is no pen at all.
+pub type Pen = u8;
+
+/// Points joined by lines drawn with the pen down. A single point is a dot.
+pub type Polyline = Vec<Point>;
+
+/// The lines a list of commands draws, grouped by the pen they're drawn with, along with the
+/// instructions that don't draw anything.
+///
+/// Instructions are kept with the pen that was selected when they were given, along with where
+/// they came among that pen's polylines, and are written back out in the same place, so an `LT`
+/// given partway through only applies to the lines drawn after it. Lines drawn with no pen
+/// selected are kept under pen `0`.
+///
+/// Something like a `VS` carries over to the next pen, so each pen's instructions start with
+/// whatever it had left over from the pens before it in the original file, and when the drawing
+/// is written out, anything left over from the pen before is set back to how the preamble left
+/// it. That way the pens can be written in any order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Drawing {
+    /// Instructions given before any pen was selected or anything was drawn, such as `IN` and
+    /// `IP`.
+    pub preamble: Vec<Command>,
+    pub layers: BTreeMap<Pen, Vec<Polyline>>,
+    /// Instructions given while each pen was selected, in the order they were given, each with
+    /// how many of that pen's polylines were drawn before it.
+    pub settings: BTreeMap<Pen, Vec<(usize, Command)>>,
+}
+
+impl Drawing {
+    /// Canonicalizes commands (see `canonicalize_with_options`), and collects what they draw.
+    ///
+    /// Labels that aren't expanded are kept as instructions, and so lose track of where they're
+    /// drawn - `expand_labels` should be set if there are any.
+    pub fn from_commands(
+        cmds: Vec<Command>,
+        options: &CanonicalizeOptions,
+    ) -> Result<Drawing, InterpretError> {
+        Ok(Drawing::from_canonical(canonicalize_with_options(
+            cmds, options,
+        )?))
+    }
+
+    pub fn from_canonical<I: IntoIterator<Item = CanonicalCommand>>(cmds: I) -> Drawing {
+        let mut builder = DrawingBuilder::new();
+        for cmd in cmds {
+            builder.push(cmd);
+        }
+        builder.finish()
+    }
+
+    /// Every polyline in the drawing, along with the pen it's drawn with.
+    pub fn polylines(&self) -> impl Iterator<Item = (Pen, &Polyline)> {
+        self.layers
+            .iter()
+            .flat_map(|(pen, polylines)| polylines.iter().map(move |p| (*pen, p)))
+    }
+
+    /// Simplifies every polyline in the drawing (see `simplify`), so that nothing moves by more
+    /// than `tolerance` plotter units.
+    pub fn simplify(&mut self, tolerance: f64) {
+        for polyline in self.layers.values_mut().flatten() {
+            *polyline = simplify(polyline, tolerance);
+        }
+    }
+
+    /// Cuts every polyline in the drawing down to the parts of it inside of `region`, which may
+    /// break it up into several polylines.
+    pub fn clip(&mut self, region: &ClipRegion) {
+        let pens: Vec<Pen> = self.layers.keys().copied().collect();
+        for pen in pens {
+            self.map_runs(pen, |run| {
+                run.iter()
+                    .flat_map(|polyline| region.clip_polyline(polyline))
+                    .collect()
+            });
+        }
+    }
+
+    /// Replaces each run of a pen's polylines that doesn't have any of its instructions in
+    /// between with what `f` returns for it, keeping the instructions between the same runs. This
+    /// lets lines be reordered, joined up or dropped without moving them past something like an
+    /// `LT` that applies to them.
+    pub fn map_runs<F>(&mut self, pen: Pen, mut f: F)
+    where
+        F: FnMut(Vec<Polyline>) -> Vec<Polyline>,
+    {
+        let polylines = match self.layers.get_mut(&pen) {
+            Some(polylines) => std::mem::take(polylines),
+            None => return,
+        };
+        let settings = self.settings.get_mut(&pen);
+        // the instructions are in order, so the runs end where they are
+        let mut ends: Vec<usize> = settings
+            .iter()
+            .flat_map(|s| s.iter().map(|(i, _)| *i))
+            .collect();
+        ends.push(polylines.len());
+        ends.dedup();
+        let mut out = vec![];
+        let mut moved = BTreeMap::new();
+        let mut rest = polylines.into_iter();
+        let mut start = 0;
+        for end in ends {
+            let run: Vec<Polyline> = rest.by_ref().take(end - start).collect();
+            if !run.is_empty() {
+                out.extend(f(run));
+            }
+            moved.insert(end, out.len());
+            start = end;
+        }
+        for (index, _) in settings.into_iter().flatten() {
+            *index = moved[&*index];
+        }
+        self.layers.insert(pen, out);
+    }
+
+    /// Every pen that draws something or has instructions, with the ones in `first` first, in
+    /// that order, and then the rest in order by number.
+    pub fn pens(&self, first: &[Pen]) -> Vec<Pen> {
+        let mut rest: Vec<Pen> = self
+            .layers
+            .keys()
+            .chain(self.settings.keys())
+            .copied()
+            .filter(|pen| !first.contains(pen))
+            .collect();
+        rest.sort_unstable();
+        rest.dedup();
+        let mut pens: Vec<Pen> = vec![];
+        for pen in first {
+            if !pens.contains(pen)
+                && (self.layers.contains_key(pen) || self.settings.contains_key(pen))
+            {
+                pens.push(*pen);
+            }
+        }
+        pens.extend(rest);
+        pens
+    }
+
+    /// Writes the drawing back out as commands: the preamble, and then each pen in order, with
+    /// its polylines, each as a `PA` to its start followed by a `PD` and a `PA` through the rest
+    /// of it, and its instructions in between them, wherever they were given. The pen is put away
+    /// with `SP0` at the end.
+    pub fn to_commands(&self) -> Vec<Command> {
+        self.to_commands_with_pen_order(&[])
+    }
+
+    /// Writes the drawing back out as commands, like `to_commands`, but with the pens in `first`
+    /// drawn first, in that order (see `pens`). Each pen is still only selected once.
+    pub fn to_commands_with_pen_order(&self, first: &[Pen]) -> Vec<Command> {
+        let mut out = self.preamble.clone();
+        let base = Modes::after(&self.preamble);
+        let mut modes = base.clone();
+        for pen in self.pens(first) {
+            let polylines = self.layers.get(&pen).map_or(&[][..], |p| &p[..]);
+            let mut settings = self.settings.get(&pen).map_or(&[][..], |s| &s[..]);
+            if polylines.is_empty() && settings.is_empty() {
+                continue;
+            }
+            let block = out.len();
+            out.push(Command::SelectPen(pen));
+            // put back anything the pen before changed, unless this pen sets it before it draws
+            // anything
+            let leading: Vec<&Command> = settings
+                .iter()
+                .take_while(|(i, cmd)| *i == 0 && Mode::of(cmd).is_some())
+                .map(|(_, cmd)| cmd)
+                .collect();
+            let mut start = base.clone();
+            for cmd in &leading {
+                start.apply(cmd);
+            }
+            for mode in modes.differences(&start) {
+                if !leading.iter().any(|cmd| Mode::of(cmd) == Some(mode)) {
+                    out.push(start.command(mode));
+                }
+            }
+            out.extend(take_settings(&mut settings, 0));
+            for (i, polyline) in polylines.iter().enumerate() {
+                let (start, rest) = match polyline.split_first() {
+                    Some(split) => split,
+                    None => continue,
+                };
+                out.push(Command::PenUp(vec![]));
+                // after lifting the pen, so that reading them back in doesn't break up the
+                // polyline before
+                out.extend(take_settings(&mut settings, i));
+                out.push(Command::PlotAbsolute(vec![*start]));
+                out.push(Command::PenDown(vec![]));
+                if !rest.is_empty() {
+                    out.push(Command::PlotAbsolute(rest.to_vec()));
+                }
+            }
+            out.push(Command::PenUp(vec![]));
+            out.extend(settings.iter().map(|(_, cmd)| cmd.clone()));
+            for cmd in &out[block..] {
+                modes.apply(cmd);
+            }
+        }
+        out.push(Command::SelectPen(0));
+        out
+    }
+}
+
+/// Something an instruction sets that carries over to whatever's drawn next, even with another
+/// pen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Mode {
+    P1P2,
+    Velocity,
+    LineType,
+    TickLength,
+    Terminator,
+    StandardSet,
+    AlternateSet,
+    SelectedSet,
+    Direction,
+    CharacterSize,
+    Slant,
+    Symbol,
+    InputMask,
+}
+
+impl Mode {
+    fn of(cmd: &Command) -> Option<Mode> {
+        Some(match cmd {
+            Command::InputP1P2(_) => Mode::P1P2,
+            Command::VelocitySelect(_) => Mode::Velocity,
+            Command::LineType(_) => Mode::LineType,
+            Command::TickLength(_) => Mode::TickLength,
+            Command::DefineTerminator(_) => Mode::Terminator,
+            Command::DesignateStandardSet(_) => Mode::StandardSet,
+            Command::DesignateAlternateSet(_) => Mode::AlternateSet,
+            Command::SelectStandardSet | Command::SelectAlternateSet => Mode::SelectedSet,
+            Command::AbsoluteDirection(_) | Command::RelativeDirection(_) => Mode::Direction,
+            Command::AbsoluteCharacterSize(_) | Command::RelativeCharacterSize(_) => {
+                Mode::CharacterSize
+            }
+            Command::CharacterSlant(_) => Mode::Slant,
+            Command::SymbolMode(_) => Mode::Symbol,
+            Command::InputMask(_) => Mode::InputMask,
+            _ => return None,
+        })
+    }
+
+    /// The instruction that sets it back to its default.
+    fn reset(self) -> Command {
+        match self {
+            Mode::P1P2 => Command::InputP1P2(None),
+            Mode::Velocity => Command::VelocitySelect(None),
+            Mode::LineType => Command::LineType(None),
+            Mode::TickLength => Command::TickLength(None),
+            Mode::Terminator => Command::DefineTerminator(None),
+            Mode::StandardSet => Command::DesignateStandardSet(None),
+            Mode::AlternateSet => Command::DesignateAlternateSet(None),
+            Mode::SelectedSet => Command::SelectStandardSet,
+            Mode::Direction => Command::AbsoluteDirection(None),
+            Mode::CharacterSize => Command::RelativeCharacterSize(None),
+            Mode::Slant => Command::CharacterSlant(None),
+            Mode::Symbol => Command::SymbolMode(None),
+            Mode::InputMask => Command::InputMask(None),
+        }
+    }
+}
+
+/// What each mode is set to, as the instruction that last set it. Modes that are at their
+/// defaults are left out.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Modes(BTreeMap<Mode, Command>);
+
+impl Modes {
+    fn after(cmds: &[Command]) -> Modes {
+        let mut modes = Modes::default();
+        for cmd in cmds {
+            modes.apply(cmd);
+        }
+        modes
+    }
+
+    fn apply(&mut self, cmd: &Command) {
+        match cmd {
+            Command::Initialize => self.0.clear(),
+            // unlike IN, DF doesn't touch the scaling points
+            Command::Default => self.0.retain(|mode, _| *mode == Mode::P1P2),
+            // like the plotter, ignore directions that don't point anywhere
+            Command::AbsoluteDirection(Some((run, rise)))
+            | Command::RelativeDirection(Some((run, rise)))
+                if *run == 0.0 && *rise == 0.0 => {}
+            Command::InputP1P2(Some((p1, None))) => {
+                // P2 follows P1, keeping the same offset, so write down where it ends up
+                let (old_p1, old_p2) = match self.0.get(&Mode::P1P2) {
+                    Some(Command::InputP1P2(Some((p1, Some(p2))))) => (*p1, *p2),
+                    _ => (DEFAULT_P1, DEFAULT_P2),
+                };
+                let p2 = Point {
+                    x: p1.x.saturating_add(old_p2.x.saturating_sub(old_p1.x)),
+                    y: p1.y.saturating_add(old_p2.y.saturating_sub(old_p1.y)),
+                };
+                self.0
+                    .insert(Mode::P1P2, Command::InputP1P2(Some((*p1, Some(p2)))));
+            }
+            cmd => {
+                if let Some(mode) = Mode::of(cmd) {
+                    if *cmd == mode.reset() {
+                        self.0.remove(&mode);
+                    } else {
+                        self.0.insert(mode, cmd.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The instruction that sets `mode` to what it is here.
+    fn command(&self, mode: Mode) -> Command {
+        self.0.get(&mode).cloned().unwrap_or_else(|| mode.reset())
+    }
+
+    /// The modes that are set differently here and in `other`.
+    fn differences(&self, other: &Modes) -> Vec<Mode> {
+        let mut modes: Vec<Mode> = self.0.keys().chain(other.0.keys()).copied().collect();
+        modes.sort_unstable();
+        modes.dedup();
+        modes.retain(|mode| self.0.get(mode) != other.0.get(mode));
+        modes
+    }
+}
+
+/// Takes the instructions given before `count` polylines were drawn off of the front of
+/// `settings`.
+fn take_settings<'a>(
+    settings: &mut &'a [(usize, Command)],
+    count: usize,
+) -> impl Iterator<Item = Command> + 'a {
+    let n = settings.iter().take_while(|(i, _)| *i <= count).count();
+    let (now, later) = settings.split_at(n);
+    *settings = later;
+    now.iter().map(|(_, cmd)| cmd.clone())
+}
+
+/// Builds a `Drawing` from canonical commands, one at a time.
+#[derive(Debug)]
+pub struct DrawingBuilder {
+    drawing: Drawing,
+    /// The selected pen, or `None` if there hasn't been a `SP` yet.
+    pen: Option<Pen>,
+    position: Point,
+    pen_down: bool,
+    /// The polyline being drawn, if the pen is down and has drawn something since it went down or
+    /// since the last instruction.
+    current: Option<Polyline>,
+    /// The modes as the preamble left them.
+    base: Modes,
+    modes: Modes,
+    /// The modes each pen had when another one was selected.
+    pen_modes: BTreeMap<Pen, Modes>,
+}
+
+impl Default for DrawingBuilder {
+    fn default() -> DrawingBuilder {
+        DrawingBuilder::new()
+    }
+}
+
+impl DrawingBuilder {
+    pub fn new() -> DrawingBuilder {
+        DrawingBuilder {
+            drawing: Drawing::default(),
+            pen: None,
+            position: Point { x: 0, y: 0 },
+            pen_down: false,
+            current: None,
+            base: Modes::default(),
+            modes: Modes::default(),
+            pen_modes: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, cmd: CanonicalCommand) {
+        match cmd {
+            CanonicalCommand::PenUp => self.lift_pen(),
+            CanonicalCommand::PenDown => {
+                if !self.pen_down {
+                    self.pen_down = true;
+                    self.current = Some(vec![self.position]);
+                }
+            }
+            CanonicalCommand::PlotAbsolute(point) => {
+                match &mut self.current {
+                    Some(current) => current.push(point),
+                    None if self.pen_down => self.current = Some(vec![self.position, point]),
+                    None => {}
+                }
+                self.position = point;
+            }
+            CanonicalCommand::SelectPen(pen) => {
+                self.lift_pen();
+                if let Some(old) = self.pen.replace(pen) {
+                    self.pen_modes.insert(old, self.modes.clone());
+                }
+                // the pen starts out with whatever the pen before left set, which it has to set
+                // itself, since it may not come after the same pen when it's written out
+                let start = self.pen_modes.get(&pen).unwrap_or(&self.base);
+                for mode in self.modes.differences(start) {
+                    self.push_setting(pen, self.modes.command(mode));
+                }
+            }
+            CanonicalCommand::Initialize => {
+                self.lift_pen();
+                self.push_instruction(Command::Initialize);
+            }
+            // an instruction given with the pen down breaks the polyline up, so that it still
+            // only applies to what's drawn after it
+            CanonicalCommand::Other(cmd) => {
+                self.end_polyline();
+                self.push_instruction(cmd);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Drawing {
+        self.end_polyline();
+        // pens that don't draw anything don't need their modes set, since every pen sets its own
+        let layers = &self.drawing.layers;
+        self.drawing.settings.retain(|pen, settings| {
+            layers.contains_key(pen) || settings.iter().any(|(_, cmd)| Mode::of(cmd).is_none())
+        });
+        self.drawing
+    }
+
+    fn push_instruction(&mut self, cmd: Command) {
+        self.modes.apply(&cmd);
+        match self.pen {
+            Some(pen) => self.push_setting(pen, cmd),
+            None if !self.drawing.layers.contains_key(&0) => {
+                self.base.apply(&cmd);
+                self.drawing.preamble.push(cmd);
+            }
+            None => self.push_setting(0, cmd),
+        }
+    }
+
+    /// Adds an instruction to a pen's settings, once it's been applied to `modes`.
+    fn push_setting(&mut self, pen: Pen, cmd: Command) {
+        let index = self.drawing.layers.get(&pen).map_or(0, Vec::len);
+        let settings = self.drawing.settings.entry(pen).or_default();
+        let mode = match Mode::of(&cmd) {
+            Some(mode) if index == 0 && settings.iter().all(|(_, c)| Mode::of(c).is_some()) => mode,
+            _ => {
+                settings.push((index, cmd));
+                return;
+            }
+        };
+        // before the pen draws anything, only the last of each mode it sets matters, and only if
+        // it's different from how the preamble left it, which is where it starts when it's
+        // written out
+        settings.retain(|(_, c)| Mode::of(c) != Some(mode));
+        if self.modes.0.get(&mode) != self.base.0.get(&mode) {
+            settings.push((0, self.modes.command(mode)));
+        }
+        if settings.is_empty() {
+            self.drawing.settings.remove(&pen);
+        }
+    }
+
+    fn lift_pen(&mut self) {
+        self.end_polyline();
+        self.pen_down = false;
+    }
+
+    fn end_polyline(&mut self) {
+        if let Some(polyline) = self.current.take() {
+            self.drawing
+                .layers
+                .entry(self.pen.unwrap_or(0))
+                .or_default()
+                .push(polyline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_commands, serialize, Style};
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_drawing() {
+        let cmds = parse_commands(
+            "IN;PD 5,5;PU;SP1;VS 10;PA 0,0;PD 100,0,100,100;PU 200,200;PD;PU;SP2;PR 10,10;PD 0,10;IN;PD 10,0;SP1;PU 0,0;PD 0,50;"
+                .to_string(),
+        )
+        .unwrap();
+        let drawing = Drawing::from_commands(cmds, &CanonicalizeOptions::default()).unwrap();
+
+        let mut layers = BTreeMap::new();
+        layers.insert(0, vec![vec![p(0, 0), p(5, 5)]]);
+        layers.insert(
+            1,
+            vec![
+                vec![p(0, 0), p(100, 0), p(100, 100)],
+                // a dot
+                vec![p(200, 200)],
+                vec![p(0, 0), p(0, 50)],
+            ],
+        );
+        layers.insert(
+            2,
+            vec![vec![p(210, 210), p(210, 220)], vec![p(210, 220), p(10, 0)]],
+        );
+        assert_eq!(drawing.layers, layers);
+        assert_eq!(drawing.preamble, vec![Command::Initialize]);
+        let mut settings = BTreeMap::new();
+        settings.insert(
+            1,
+            vec![
+                (0, Command::VelocitySelect(Some(10.0))),
+                // pen 1's last line comes after pen 2's IN, which set the speed back
+                (2, Command::VelocitySelect(None)),
+            ],
+        );
+        settings.insert(
+            2,
+            vec![
+                // left over from pen 1
+                (0, Command::VelocitySelect(Some(10.0))),
+                // given after pen 2's first polyline
+                (1, Command::Initialize),
+            ],
+        );
+        assert_eq!(drawing.settings, settings);
+        assert_eq!(drawing.polylines().count(), 6);
+
+        assert_eq!(
+            serialize(&drawing.to_commands(), Style::Compact).unwrap(),
+            "IN;SP0;PU;PA0,0;PD;PA5,5;PU;\
+             SP1;VS10;PU;PA0,0;PD;PA100,0,100,100;PU;PA200,200;PD;PU;VS;PA0,0;PD;PA0,50;PU;\
+             SP2;VS10;PU;PA210,210;PD;PA210,220;PU;IN;PA210,220;PD;PA10,0;PU;SP0;"
+        );
+        // writing a drawing out and reading it back in doesn't change it
+        let round_trip = Drawing::from_commands(drawing.to_commands(), &Default::default());
+        assert_eq!(round_trip.unwrap(), drawing);
+    }
+
+    #[test]
+    fn test_instruction_order() {
+        let cmds = parse_commands(
+            "SP1;LT 2;PD 100,0;PU 0,100;LT;PD 100,100;VS 10;PD 100,200;PU;VS 20;".to_string(),
+        )
+        .unwrap();
+        let mut drawing = Drawing::from_commands(cmds, &Default::default()).unwrap();
+        assert_eq!(
+            drawing.layers[&1],
+            vec![
+                vec![p(0, 0), p(100, 0)],
+                vec![p(0, 100), p(100, 100)],
+                // the VS broke the line up
+                vec![p(100, 100), p(100, 200)],
+            ]
+        );
+        assert_eq!(
+            drawing.settings[&1],
+            vec![
+                (0, Command::LineType(Some((2, None)))),
+                (1, Command::LineType(None)),
+                (2, Command::VelocitySelect(Some(10.0))),
+                (3, Command::VelocitySelect(Some(20.0))),
+            ]
+        );
+        assert_eq!(
+            serialize(&drawing.to_commands(), Style::Compact).unwrap(),
+            "SP1;LT2;PU;PA0,0;PD;PA100,0;PU;LT;PA0,100;PD;PA100,100;\
+             PU;VS10;PA100,100;PD;PA100,200;PU;VS20;SP0;"
+        );
+        let round_trip = Drawing::from_commands(drawing.to_commands(), &Default::default());
+        assert_eq!(round_trip.unwrap(), drawing);
+
+        // lines can be reordered and joined up, but not past an instruction
+        drawing.map_runs(1, |mut run| {
+            assert_eq!(run.len(), 1);
+            run.push(vec![p(5, 5)]);
+            run
+        });
+        assert_eq!(drawing.layers[&1].len(), 6);
+        let indices: Vec<usize> = drawing.settings[&1].iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_clip() {
+        let cmds = parse_commands("SP1;PD 200,0,200,200;PU -50,50;PD;PU;SP2;PD 0,0;".to_string());
+        let mut drawing = Drawing::from_commands(cmds.unwrap(), &Default::default()).unwrap();
+        drawing.clip(&ClipRegion::Rectangle(p(0, 0), p(100, 100)));
+        assert_eq!(drawing.layers[&1], vec![vec![p(0, 0), p(100, 0)]]);
+        // a line that only touches the corner is left out
+        assert_eq!(drawing.layers[&2], Vec::<Polyline>::new());
+    }
+
+    #[test]
+    fn test_pen_order() {
+        let cmds = parse_commands(
+            "SP3;PD 10,0;SP1;PU 0,0;PD 0,10;SP2;VS 10;PU 50,50;PD 60,60;SP4;SI 1,1;SP3;PD 20,0;"
+                .to_string(),
+        );
+        let drawing = Drawing::from_commands(cmds.unwrap(), &Default::default()).unwrap();
+        // pen 4 doesn't draw anything, so it doesn't need its modes set
+        assert_eq!(drawing.pens(&[]), vec![1, 2, 3]);
+        // pens that aren't used are skipped, and ones that aren't listed come last
+        assert_eq!(drawing.pens(&[3, 5, 3]), vec![3, 1, 2]);
+        // pen 3's second line is drawn with what pens 2 and 4 set, and the pens after it get
+        // their own back
+        let out = drawing.to_commands_with_pen_order(&[3, 2]);
+        assert_eq!(
+            serialize(&out, Style::Compact).unwrap(),
+            "SP3;PU;PA0,0;PD;PA10,0;PU;VS10;SI1,1;PA60,60;PD;PA20,0;PU;\
+             SP2;SR;VS10;PU;PA50,50;PD;PA60,60;PU;\
+             SP1;VS;PU;PA0,0;PD;PA0,10;PU;SP0;"
+        );
+        let round_trip = Drawing::from_commands(out.clone(), &Default::default()).unwrap();
+        assert_eq!(round_trip.to_commands_with_pen_order(&[3, 2]), out);
+    }
+}
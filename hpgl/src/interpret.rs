@@ -0,0 +1,1339 @@
+use crate::clip::ClipRegion;
+use crate::command::{CharacterStroke, Command, Scaling};
+use crate::label::{glyph, user_defined_character, Lettering};
+use crate::Point;
+use std::fmt;
+
+/// What the plotter does in response to a command.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// The pen moved while it was down, drawing a line. `pen` is `0` if no pen is selected, in
+    /// which case nothing actually ends up on the paper.
+    Stroke {
+        pen: u8,
+        from: Point,
+        to: Point,
+    },
+    /// The pen moved while it was up, or while it was down but outside of the window set by `IW`,
+    /// where the plotter lifts the pen.
+    Travel {
+        from: Point,
+        to: Point,
+    },
+    /// A `PU`, whether or not the pen was already up, or the pen being lifted by `CI`.
+    PenUp,
+    /// A `PD`, whether or not the pen was already down, or the pen being lowered by `CI`.
+    PenDown,
+    SelectPen(u8),
+    Initialize,
+    /// A command that doesn't move the pen. Any state it sets is tracked by the interpreter, and
+    /// it's passed on so that it can be written back out.
+    Other(Command),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InterpretError {
+    /// A relative move took the pen outside of the representable coordinate range.
+    CoordinateOverflow { position: Point, offset: Point },
+    /// A point in user units (see `SC`) is outside of the representable coordinate range once
+    /// it's been scaled to plotter units.
+    ScaledOutOfRange { point: Point },
+    /// Part of a label is outside of the representable coordinate range.
+    LabelOutOfRange { position: Point },
+    /// Part of an arc or circle is outside of the representable coordinate range.
+    ArcOutOfRange { center: Point, radius: i64 },
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpretError::CoordinateOverflow { position, offset } => write!(
+                f,
+                "relative move by ({}, {}) from ({}, {}) is out of range",
+                offset.x, offset.y, position.x, position.y
+            ),
+            InterpretError::ScaledOutOfRange { point } => write!(
+                f,
+                "({}, {}) in user units is out of range in plotter units",
+                point.x, point.y
+            ),
+            InterpretError::LabelOutOfRange { position } => write!(
+                f,
+                "label at ({}, {}) is out of range",
+                position.x, position.y
+            ),
+            InterpretError::ArcOutOfRange { center, radius } => write!(
+                f,
+                "arc of radius {} around ({}, {}) is out of range",
+                radius, center.x, center.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+/// The default scaling points of the HP7440A, which `IN` and `IP;` go back to.
+pub const DEFAULT_P1: Point = Point { x: 250, y: 279 };
+pub const DEFAULT_P2: Point = Point { x: 10250, y: 7479 };
+
+/// The chord angle used by `CI`, `AA` and `AR` when none is given, in degrees.
+pub const DEFAULT_CHORD_ANGLE: f64 = 5.0;
+
+/// Everything about the plotter that affects how later commands behave.
+#[derive(Debug, PartialEq, Clone)]
+pub struct State {
+    /// Where the pen is, in plotter units.
+    pub position: Point,
+    /// Whether points are absolute (`PA`) or relative (`PR`).
+    pub absolute: bool,
+    pub pen_down: bool,
+    /// The selected pen, or `0` if there isn't one.
+    pub pen: u8,
+    /// The scaling points set by `IP`.
+    pub p1: Point,
+    pub p2: Point,
+    /// The user units set by `SC`, or `None` if points are in plotter units.
+    pub scaling: Option<Scaling>,
+    /// The lower left and upper right corners of the window set by `IW`, or `None` if drawing is
+    /// only limited by the plotter itself.
+    pub window: Option<(Point, Point)>,
+    /// The pen speed set by `VS`, in cm/s, or `None` for the plotter's default.
+    pub velocity: Option<f64>,
+    /// The pattern number and pattern length set by `LT`, or `None` for solid lines. The length
+    /// is in percent of the distance between P1 and P2.
+    pub line_type: Option<(u8, f64)>,
+    pub lettering: Lettering,
+}
+
+impl Default for State {
+    fn default() -> State {
+        State {
+            position: Point { x: 0, y: 0 },
+            absolute: true,
+            pen_down: false,
+            pen: 0,
+            p1: DEFAULT_P1,
+            p2: DEFAULT_P2,
+            scaling: None,
+            window: None,
+            velocity: None,
+            line_type: None,
+            lettering: Lettering::default(),
+        }
+    }
+}
+
+/// The pattern length used by `LT` when none is given, in percent of the distance between P1 and
+/// P2.
+pub const DEFAULT_PATTERN_LENGTH: f64 = 4.0;
+
+/// The parts of each `LT` pattern that are drawn with the pen down, as fractions of the pattern
+/// length. Parts that start and end at the same place are dots. Pattern 0 isn't here, since it
+/// isn't a repeating pattern - it only puts a dot at the end of each line.
+const LINE_TYPES: [&[(f64, f64)]; 6] = [
+    &[(0.0, 0.0)],
+    &[(0.0, 0.5)],
+    &[(0.0, 0.7)],
+    &[(0.0, 0.8), (0.9, 0.9)],
+    &[(0.0, 0.7), (0.8, 0.9)],
+    &[(0.0, 0.5), (0.6, 0.7), (0.8, 0.9)],
+];
+
+/// Executes commands against a model of the plotter, turning them into the movements the plotter
+/// would make.
+#[derive(Debug, Default, Clone)]
+pub struct Interpreter {
+    state: State,
+    expand_line_types: bool,
+    /// How far into the line type pattern the pen is, in plotter units.
+    pattern_offset: f64,
+    expand_labels: bool,
+    /// Where a carriage return in a label goes back to, which is where the first of a run of
+    /// labels started.
+    carriage_return: Option<(f64, f64)>,
+    /// Exactly where the last label ended, so that rounding to plotter units doesn't build up
+    /// over a run of labels.
+    label_end: Option<(f64, f64)>,
+    /// The area the pen can reach, or `None` if it can go anywhere.
+    hard_clip_limits: Option<(Point, Point)>,
+    /// Where drawing is limited to, on top of the window and the hard clip limits.
+    clip_region: Option<ClipRegion>,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Whether dashed and dotted lines set with `LT` should be broken up into strokes and travels
+    /// the way the plotter draws them, rather than being drawn as solid strokes. This is off by
+    /// default, since a plotter given the solid strokes along with the `LT` will draw the same
+    /// thing.
+    pub fn set_expand_line_types(&mut self, expand: bool) {
+        self.expand_line_types = expand;
+    }
+
+    /// Whether labels (`LB` and `UC`) should be drawn as strokes and travels, using the plotter's
+    /// character set 0, rather than only moving the pen to where the label ends. Like
+    /// `set_expand_line_types`, this is off by default, and a plotter will draw the same thing
+    /// either way.
+    pub fn set_expand_labels(&mut self, expand: bool) {
+        self.expand_labels = expand;
+    }
+
+    /// Limits drawing to the area the pen can reach, given by its lower left and upper right
+    /// corners (see `device::Model::hard_clip_limits`). Like the window set by `IW`, the parts
+    /// of lines outside of it become travels. By default, the pen can reach anywhere.
+    pub fn set_hard_clip_limits(&mut self, limits: Option<(Point, Point)>) {
+        self.hard_clip_limits = limits;
+    }
+
+    /// Limits drawing to a region of the page, which can be any polygon. It works the same way
+    /// as the hard clip limits, so the parts of lines outside of it become travels.
+    pub fn set_clip_region(&mut self, region: Option<ClipRegion>) {
+        self.clip_region = region;
+    }
+
+    /// Whether putting the pen down at a point leaves a mark, which it doesn't outside of the
+    /// window, the hard clip limits or the clip region.
+    pub fn draws_at(&self, point: Point) -> bool {
+        self.state
+            .window
+            .iter()
+            .chain(&self.hard_clip_limits)
+            .all(|window| clip_to_window(point, point, *window).is_some())
+            && self.clip_region.iter().all(|region| region.contains(point))
+    }
+
+    /// Executes a single command, returning what the plotter did in response.
+    pub fn execute(&mut self, cmd: Command) -> Result<Vec<Event>, InterpretError> {
+        let mut events = vec![];
+        if matches!(
+            cmd,
+            Command::PenUp(_)
+                | Command::PenDown(_)
+                | Command::PlotAbsolute(_)
+                | Command::PlotRelative(_)
+                | Command::Circle(..)
+                | Command::ArcAbsolute(..)
+                | Command::ArcRelative(..)
+                | Command::Initialize
+                | Command::Default
+        ) {
+            // moving the pen any other way starts a new run of labels
+            self.carriage_return = None;
+            self.label_end = None;
+        }
+        match cmd {
+            Command::PenUp(points) => {
+                self.set_pen_down(false, &mut events);
+                self.move_to(&points, &mut events)?;
+            }
+            Command::PenDown(points) => {
+                self.set_pen_down(true, &mut events);
+                self.move_to(&points, &mut events)?;
+            }
+            Command::PlotAbsolute(points) => {
+                self.state.absolute = true;
+                self.move_to(&points, &mut events)?;
+            }
+            Command::PlotRelative(points) => {
+                self.state.absolute = false;
+                self.move_to(&points, &mut events)?;
+            }
+            Command::Circle(radius, chord_angle) => {
+                self.circle(radius, chord_angle, &mut events)?;
+            }
+            Command::ArcAbsolute(center, angle, chord_angle) => {
+                let center = self.scale(center)?;
+                self.arc(center, angle, chord_angle, &mut events)?;
+            }
+            Command::ArcRelative(offset, angle, chord_angle) => {
+                let center = self.offset(offset)?;
+                self.arc(center, angle, chord_angle, &mut events)?;
+            }
+            Command::SelectPen(pen) => {
+                self.state.pen = pen;
+                events.push(Event::SelectPen(pen));
+            }
+            Command::Initialize => {
+                // IN lifts the pen and resets everything else, but doesn't move the pen or put
+                // it away
+                self.state = State {
+                    position: self.state.position,
+                    pen: self.state.pen,
+                    ..State::default()
+                };
+                self.pattern_offset = 0.0;
+                events.push(Event::Initialize);
+            }
+            cmd => {
+                self.state.lettering.apply(&cmd);
+                match &cmd {
+                    Command::Label(text) => self.label(text, &mut events)?,
+                    Command::CharacterPlot(spaces_and_lines) => {
+                        self.character_plot(*spaces_and_lines, &mut events)?
+                    }
+                    Command::UserDefinedCharacter(strokes) => {
+                        self.user_defined_character(strokes, &mut events)?
+                    }
+                    Command::Default => {
+                        // unlike IN, DF doesn't touch the pen or the scaling points
+                        self.state = State {
+                            position: self.state.position,
+                            pen_down: self.state.pen_down,
+                            pen: self.state.pen,
+                            p1: self.state.p1,
+                            p2: self.state.p2,
+                            ..State::default()
+                        };
+                        self.pattern_offset = 0.0;
+                    }
+                    Command::InputP1P2(None) => {
+                        self.state.p1 = DEFAULT_P1;
+                        self.state.p2 = DEFAULT_P2;
+                    }
+                    Command::InputP1P2(Some((p1, p2))) => {
+                        // if only P1 is given, P2 follows it, keeping the same offset
+                        let p2 = p2.unwrap_or(Point {
+                            x: p1
+                                .x
+                                .saturating_add(self.state.p2.x.saturating_sub(self.state.p1.x)),
+                            y: p1
+                                .y
+                                .saturating_add(self.state.p2.y.saturating_sub(self.state.p1.y)),
+                        });
+                        self.state.p1 = *p1;
+                        self.state.p2 = p2;
+                    }
+                    // like the plotter, ignore scaling that would map a whole axis onto a point
+                    Command::Scale(Some(s)) if s.x_min == s.x_max || s.y_min == s.y_max => {}
+                    Command::Scale(scaling) => self.state.scaling = *scaling,
+                    Command::InputWindow(window) => self.state.window = *window,
+                    Command::VelocitySelect(velocity) => self.state.velocity = *velocity,
+                    Command::LineType(None) => {
+                        self.state.line_type = None;
+                        self.pattern_offset = 0.0;
+                    }
+                    // like the plotter, ignore patterns and lengths that are out of range
+                    Command::LineType(Some((pattern, length))) => {
+                        let length = length.unwrap_or(DEFAULT_PATTERN_LENGTH);
+                        if *pattern <= 6 && length > 0.0 && length <= 100.0 {
+                            self.state.line_type = Some((*pattern, length));
+                            self.pattern_offset = 0.0;
+                        }
+                    }
+                    _ => {}
+                }
+                events.push(Event::Other(cmd));
+            }
+        }
+        Ok(events)
+    }
+
+    fn move_to(&mut self, points: &[Point], events: &mut Vec<Event>) -> Result<(), InterpretError> {
+        for point in points {
+            let to = self.resolve(*point)?;
+            self.line_to(to, events);
+        }
+        Ok(())
+    }
+
+    /// Moves the pen in a straight line to an absolute position, drawing a line if the pen is
+    /// down.
+    fn line_to(&mut self, to: Point, events: &mut Vec<Event>) {
+        let from = self.state.position;
+        self.state.position = to;
+        if !self.state.pen_down {
+            events.push(Event::Travel { from, to });
+            return;
+        }
+        match self.state.line_type {
+            Some((pattern, length)) if self.expand_line_types => {
+                self.dashed_line(from, to, pattern, length, events)
+            }
+            _ => self.stroke(from, to, events),
+        }
+    }
+
+    /// Draws a line with the current line type, lifting the pen for the gaps in the pattern. The
+    /// pattern carries on from where the last line left off, until the pen is lifted.
+    fn dashed_line(
+        &mut self,
+        from: Point,
+        to: Point,
+        pattern: u8,
+        length: f64,
+        events: &mut Vec<Event>,
+    ) {
+        if pattern == 0 {
+            events.push(Event::Travel { from, to });
+            self.stroke(to, to, events);
+            return;
+        }
+        let diagonal = (f64::from(self.state.p2.x) - f64::from(self.state.p1.x))
+            .hypot(f64::from(self.state.p2.y) - f64::from(self.state.p1.y));
+        let length = length / 100.0 * diagonal;
+        if length < 1.0 {
+            // too small for the plotter to draw as anything other than a solid line
+            self.stroke(from, to, events);
+            return;
+        }
+
+        let (x, y) = (f64::from(from.x), f64::from(from.y));
+        let (dx, dy) = (f64::from(to.x) - x, f64::from(to.y) - y);
+        let distance = dx.hypot(dy);
+        // the line covers start..end of the repeating pattern
+        let start = self.pattern_offset;
+        let end = start + distance;
+        let at = |offset: f64| {
+            if offset >= end {
+                return to;
+            }
+            let t = (offset - start) / distance;
+            // this is between from and to, so it's in range
+            round_point(x + t * dx, y + t * dy).unwrap()
+        };
+        let mut position = from;
+        let mut period = (start / length).floor();
+        while period * length < end {
+            for (down_start, down_end) in LINE_TYPES[usize::from(pattern) - 1] {
+                let piece_start = (period + down_start) * length;
+                let piece_end = (period + down_end) * length;
+                // dots are drawn if they're anywhere on the line but the very end, so that they
+                // aren't drawn twice on consecutive lines
+                let on_line = if piece_start == piece_end {
+                    piece_start >= start && piece_start < end
+                } else {
+                    piece_start.max(start) < piece_end.min(end)
+                };
+                if !on_line {
+                    continue;
+                }
+                let piece_from = at(piece_start.max(start));
+                let piece_to = at(piece_end.min(end));
+                if piece_from != position {
+                    events.push(Event::Travel {
+                        from: position,
+                        to: piece_from,
+                    });
+                }
+                self.stroke(piece_from, piece_to, events);
+                position = piece_to;
+            }
+            period += 1.0;
+        }
+        if position != to {
+            events.push(Event::Travel { from: position, to });
+        }
+        self.pattern_offset = end % length;
+    }
+
+    /// Draws a line with the pen down. Like the plotter, only the part of the line inside both
+    /// the window and the hard clip limits (and the clip region, if there is one) is drawn.
+    fn stroke(&mut self, from: Point, to: Point, events: &mut Vec<Event>) {
+        let mut clipped = Some((from, to));
+        for window in self.state.window.iter().chain(&self.hard_clip_limits) {
+            clipped = clipped.and_then(|(start, end)| clip_to_window(start, end, *window));
+        }
+        let pieces = match (clipped, &self.clip_region) {
+            (None, _) => vec![],
+            (Some((start, end)), Some(region)) => region.clip_line(start, end),
+            (Some(clipped), None) => vec![clipped],
+        };
+        if pieces.is_empty() {
+            events.push(Event::Travel { from, to });
+            return;
+        }
+        let mut position = from;
+        for (start, end) in pieces {
+            if start != position {
+                events.push(Event::Travel {
+                    from: position,
+                    to: start,
+                });
+            }
+            events.push(Event::Stroke {
+                pen: self.state.pen,
+                from: start,
+                to: end,
+            });
+            position = end;
+        }
+        if position != to {
+            events.push(Event::Travel { from: position, to });
+        }
+    }
+
+    /// Where the next character of a label goes.
+    fn label_start(&self) -> (f64, f64) {
+        self.label_end.unwrap_or((
+            f64::from(self.state.position.x),
+            f64::from(self.state.position.y),
+        ))
+    }
+
+    fn label(&mut self, text: &str, events: &mut Vec<Event>) -> Result<(), InterpretError> {
+        let layout = self.state.lettering.layout(self.state.p1, self.state.p2);
+        let mut origin = self.label_start();
+        let carriage_return = *self.carriage_return.get_or_insert(origin);
+        let mut lines = vec![];
+        for c in text.chars() {
+            match c {
+                '\r' => origin = layout.carriage_return(origin, carriage_return),
+                '\n' => origin = layout.moved(origin, 0.0, -1.0),
+                '\x08' => origin = layout.moved(origin, -1.0, 0.0),
+                // shift out and shift in
+                '\x0e' => self.state.lettering.alternate_selected = true,
+                '\x0f' => self.state.lettering.alternate_selected = false,
+                c if c.is_control() => {}
+                c => {
+                    // only character set 0 is available, so it's used for every set
+                    for line in glyph(c).unwrap_or_default() {
+                        lines.push(
+                            line.into_iter()
+                                .map(|(x, y)| layout.point(origin, x, y))
+                                .collect(),
+                        );
+                    }
+                    origin = layout.moved(origin, 1.0, 0.0);
+                }
+            }
+        }
+        self.draw_characters(lines, origin, events)
+    }
+
+    /// `CP` moves by a number of character spaces and lines, or with no parameters, does a
+    /// carriage return and line feed.
+    fn character_plot(
+        &mut self,
+        spaces_and_lines: Option<(f64, f64)>,
+        events: &mut Vec<Event>,
+    ) -> Result<(), InterpretError> {
+        let layout = self.state.lettering.layout(self.state.p1, self.state.p2);
+        let origin = self.label_start();
+        let carriage_return = *self.carriage_return.get_or_insert(origin);
+        let end = match spaces_and_lines {
+            Some((spaces, lines)) => layout.moved(origin, spaces, lines),
+            None => layout.moved(layout.carriage_return(origin, carriage_return), 0.0, -1.0),
+        };
+        self.draw_characters(vec![], end, events)
+    }
+
+    fn user_defined_character(
+        &mut self,
+        strokes: &[CharacterStroke],
+        events: &mut Vec<Event>,
+    ) -> Result<(), InterpretError> {
+        let layout = self.state.lettering.layout(self.state.p1, self.state.p2);
+        let origin = self.label_start();
+        self.carriage_return.get_or_insert(origin);
+        let lines = user_defined_character(strokes)
+            .into_iter()
+            .map(|line| {
+                line.into_iter()
+                    .map(|(x, y)| layout.point(origin, x, y))
+                    .collect()
+            })
+            .collect();
+        self.draw_characters(lines, layout.moved(origin, 1.0, 0.0), events)
+    }
+
+    /// Draws the lines of some characters, if labels are being expanded, and then moves to where
+    /// the next character will go. Like `CI`, this lifts the pen to move between lines, and
+    /// leaves it the way it was before. Characters are always drawn with solid lines.
+    fn draw_characters(
+        &mut self,
+        lines: Vec<Vec<(f64, f64)>>,
+        end: (f64, f64),
+        events: &mut Vec<Event>,
+    ) -> Result<(), InterpretError> {
+        let out_of_range = InterpretError::LabelOutOfRange {
+            position: self.state.position,
+        };
+        let round = |(x, y)| round_point(x, y).ok_or_else(|| out_of_range.clone());
+        let end_point = round(end)?;
+        self.label_end = Some(end);
+        if !self.expand_labels {
+            self.state.position = end_point;
+            return Ok(());
+        }
+        let pen_down = self.state.pen_down;
+        for line in lines {
+            let points = line.into_iter().map(round).collect::<Result<Vec<_>, _>>()?;
+            if self.state.pen_down {
+                self.set_pen_down(false, events);
+            }
+            self.line_to(points[0], events);
+            self.set_pen_down(true, events);
+            if points.len() == 1 {
+                self.stroke(points[0], points[0], events);
+            }
+            for pair in points.windows(2) {
+                self.stroke(pair[0], pair[1], events);
+            }
+            self.state.position = points[points.len() - 1];
+        }
+        if self.state.pen_down {
+            self.set_pen_down(false, events);
+        }
+        self.line_to(end_point, events);
+        if pen_down {
+            self.set_pen_down(true, events);
+        }
+        Ok(())
+    }
+
+    fn set_pen_down(&mut self, pen_down: bool, events: &mut Vec<Event>) {
+        self.state.pen_down = pen_down;
+        if !pen_down {
+            self.pattern_offset = 0.0;
+        }
+        events.push(if pen_down {
+            Event::PenDown
+        } else {
+            Event::PenUp
+        });
+    }
+
+    /// `CI` lifts the pen to move out to the start of the circle, draws it counterclockwise, and
+    /// then lifts the pen again to go back to the center, leaving the pen up or down the way it
+    /// was before.
+    fn circle(
+        &mut self,
+        radius: i32,
+        chord_angle: Option<f64>,
+        events: &mut Vec<Event>,
+    ) -> Result<(), InterpretError> {
+        let center = self.state.position;
+        let pen_down = self.state.pen_down;
+        let arc = Arc {
+            center,
+            // the radius is in user units along the x axis
+            radius: f64::from(radius) * self.state.factors().0,
+            start: 0.0,
+        };
+        let start = arc.point(0.0)?;
+        let points = arc.chords(360.0, chord_angle)?;
+
+        self.set_pen_down(false, events);
+        self.line_to(start, events);
+        self.set_pen_down(true, events);
+        for point in points {
+            self.line_to(point, events);
+        }
+        self.set_pen_down(false, events);
+        self.line_to(center, events);
+        if pen_down {
+            self.set_pen_down(true, events);
+        }
+        Ok(())
+    }
+
+    /// `AA` and `AR` draw (or move, with the pen up) from the current position around `center`.
+    fn arc(
+        &mut self,
+        center: Point,
+        angle: f64,
+        chord_angle: Option<f64>,
+        events: &mut Vec<Event>,
+    ) -> Result<(), InterpretError> {
+        let dx = f64::from(self.state.position.x) - f64::from(center.x);
+        let dy = f64::from(self.state.position.y) - f64::from(center.y);
+        let arc = Arc {
+            center,
+            radius: dx.hypot(dy),
+            start: dy.atan2(dx).to_degrees(),
+        };
+        for point in arc.chords(angle, chord_angle)? {
+            self.line_to(point, events);
+        }
+        Ok(())
+    }
+
+    /// Turns a point given to a command into an absolute position, in plotter units.
+    fn resolve(&self, point: Point) -> Result<Point, InterpretError> {
+        if self.state.absolute {
+            self.scale(point)
+        } else {
+            self.offset(point)
+        }
+    }
+
+    /// Converts an absolute point from user units to plotter units.
+    fn scale(&self, point: Point) -> Result<Point, InterpretError> {
+        let s = match self.state.scaling {
+            Some(s) => s,
+            None => return Ok(point),
+        };
+        let (x_factor, y_factor) = self.state.factors();
+        round_point(
+            f64::from(self.state.p1.x) + (f64::from(point.x) - f64::from(s.x_min)) * x_factor,
+            f64::from(self.state.p1.y) + (f64::from(point.y) - f64::from(s.y_min)) * y_factor,
+        )
+        .ok_or(InterpretError::ScaledOutOfRange { point })
+    }
+
+    /// The absolute position, in plotter units, of a point relative to the current position.
+    fn offset(&self, point: Point) -> Result<Point, InterpretError> {
+        let position = self.state.position;
+        let (x_factor, y_factor) = self.state.factors();
+        let scaled = round_point(f64::from(point.x) * x_factor, f64::from(point.y) * y_factor)
+            .ok_or(InterpretError::ScaledOutOfRange { point })?;
+        let overflow = InterpretError::CoordinateOverflow {
+            position,
+            offset: point,
+        };
+        Ok(Point {
+            x: position
+                .x
+                .checked_add(scaled.x)
+                .ok_or_else(|| overflow.clone())?,
+            y: position.y.checked_add(scaled.y).ok_or(overflow)?,
+        })
+    }
+}
+
+impl State {
+    /// The size of a user unit along each axis, in plotter units.
+    fn factors(&self) -> (f64, f64) {
+        match self.scaling {
+            Some(s) => (
+                (f64::from(self.p2.x) - f64::from(self.p1.x))
+                    / (f64::from(s.x_max) - f64::from(s.x_min)),
+                (f64::from(self.p2.y) - f64::from(self.p1.y))
+                    / (f64::from(s.y_max) - f64::from(s.y_min)),
+            ),
+            None => (1.0, 1.0),
+        }
+    }
+}
+
+/// Clips a line to the part of it that's inside a window (given by two opposite corners), using
+/// the Liang-Barsky algorithm. Returns `None` if none of the line is inside the window.
+pub(crate) fn clip_to_window(
+    from: Point,
+    to: Point,
+    window: (Point, Point),
+) -> Option<(Point, Point)> {
+    let (a, b) = window;
+    let (x_min, x_max) = (f64::from(a.x.min(b.x)), f64::from(a.x.max(b.x)));
+    let (y_min, y_max) = (f64::from(a.y.min(b.y)), f64::from(a.y.max(b.y)));
+    let (x, y) = (f64::from(from.x), f64::from(from.y));
+    let (dx, dy) = (f64::from(to.x) - x, f64::from(to.y) - y);
+
+    // the line is from + t * (dx, dy), and the part of it inside the window is from t_enter to
+    // t_exit
+    let mut t_enter: f64 = 0.0;
+    let mut t_exit: f64 = 1.0;
+    for (p, q) in &[
+        (-dx, x - x_min),
+        (dx, x_max - x),
+        (-dy, y - y_min),
+        (dy, y_max - y),
+    ] {
+        if *p == 0.0 {
+            // parallel to this edge, so either all inside it or all outside of it
+            if *q < 0.0 {
+                return None;
+            }
+        } else if *p < 0.0 {
+            t_enter = t_enter.max(q / p);
+        } else {
+            t_exit = t_exit.min(q / p);
+        }
+    }
+    if t_enter > t_exit {
+        return None;
+    }
+    let at = |t: f64| {
+        // the window is in range, so anything clipped to it is too
+        round_point(x + t * dx, y + t * dy).unwrap()
+    };
+    let start = if t_enter == 0.0 { from } else { at(t_enter) };
+    let end = if t_exit == 1.0 { to } else { at(t_exit) };
+    Some((start, end))
+}
+
+/// Rounds a point to the nearest plotter unit, if it's in range.
+pub(crate) fn round_point(x: f64, y: f64) -> Option<Point> {
+    let (x, y) = (x.round(), y.round());
+    let range = f64::from(i32::MIN)..=f64::from(i32::MAX);
+    if range.contains(&x) && range.contains(&y) {
+        Some(Point {
+            x: x as i32,
+            y: y as i32,
+        })
+    } else {
+        None
+    }
+}
+
+/// A circle that an arc is drawn along, starting at an angle in degrees. The radius may be
+/// negative, in which case the arc starts on the opposite side of the circle.
+struct Arc {
+    center: Point,
+    radius: f64,
+    start: f64,
+}
+
+impl Arc {
+    fn point(&self, angle: f64) -> Result<Point, InterpretError> {
+        let angle = (self.start + angle).to_radians();
+        round_point(
+            f64::from(self.center.x) + self.radius * angle.cos(),
+            f64::from(self.center.y) + self.radius * angle.sin(),
+        )
+        .ok_or(InterpretError::ArcOutOfRange {
+            center: self.center,
+            radius: self.radius.round() as i64,
+        })
+    }
+
+    /// The ends of the chords that make up the arc, sweeping through `sweep` degrees
+    /// counterclockwise (or clockwise, if it's negative).
+    ///
+    /// As on the 7475A, every chord subtends the chord angle, apart from the last one, which is
+    /// shorter if the chord angle doesn't divide the sweep evenly. The chord angle is limited to
+    /// between 0.5 and 180 degrees.
+    fn chords(&self, sweep: f64, chord_angle: Option<f64>) -> Result<Vec<Point>, InterpretError> {
+        let chord_angle = chord_angle
+            .unwrap_or(DEFAULT_CHORD_ANGLE)
+            .abs()
+            .clamp(0.5, 180.0);
+        // going around more than once draws over the same line, so only the last time around
+        // needs to be drawn to end up in the same place
+        let sweep = if sweep.abs() > 360.0 {
+            sweep.signum() * (360.0 + sweep.abs() % 360.0)
+        } else {
+            sweep
+        };
+        let count = (sweep.abs() / chord_angle).ceil() as usize;
+        (1..=count)
+            .map(|i| {
+                let angle = (i as f64 * chord_angle).min(sweep.abs());
+                self.point(sweep.signum() * angle)
+            })
+            .collect()
+    }
+}
+
+/// Executes a list of commands from the plotter's initial state.
+pub fn interpret<I: IntoIterator<Item = Command>>(cmds: I) -> Result<Vec<Event>, InterpretError> {
+    let mut interpreter = Interpreter::new();
+    let mut events = vec![];
+    for cmd in cmds {
+        events.extend(interpreter.execute(cmd)?);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_commands;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_events() {
+        let cmds = parse_commands("SP2;PA 10,10;PD 20,10;PR 0,10;PU -10,0;PD;PA 0,0;".to_string());
+        assert_eq!(
+            interpret(cmds.unwrap()),
+            Ok(vec![
+                Event::SelectPen(2),
+                Event::Travel {
+                    from: p(0, 0),
+                    to: p(10, 10)
+                },
+                Event::PenDown,
+                Event::Stroke {
+                    pen: 2,
+                    from: p(10, 10),
+                    to: p(20, 10)
+                },
+                Event::Stroke {
+                    pen: 2,
+                    from: p(20, 10),
+                    to: p(20, 20)
+                },
+                Event::PenUp,
+                Event::Travel {
+                    from: p(20, 20),
+                    to: p(10, 20)
+                },
+                Event::PenDown,
+                Event::Stroke {
+                    pen: 2,
+                    from: p(10, 20),
+                    to: p(0, 0)
+                },
+            ])
+        );
+    }
+
+    fn strokes(pen: u8, points: &[Point]) -> Vec<Event> {
+        points
+            .windows(2)
+            .map(|w| Event::Stroke {
+                pen,
+                from: w[0],
+                to: w[1],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_circle() {
+        let cmds = parse_commands("SP1;PA 10,10;CI 100,90;PD;CI -10,180;".to_string());
+        let mut expected = vec![
+            Event::SelectPen(1),
+            Event::Travel {
+                from: p(0, 0),
+                to: p(10, 10),
+            },
+            Event::PenUp,
+            Event::Travel {
+                from: p(10, 10),
+                to: p(110, 10),
+            },
+            Event::PenDown,
+        ];
+        expected.extend(strokes(
+            1,
+            &[p(110, 10), p(10, 110), p(-90, 10), p(10, -90), p(110, 10)],
+        ));
+        expected.extend(vec![
+            Event::PenUp,
+            Event::Travel {
+                from: p(110, 10),
+                to: p(10, 10),
+            },
+            // the pen was up before the first circle, and down before the second
+            Event::PenDown,
+            Event::PenUp,
+            Event::Travel {
+                from: p(10, 10),
+                to: p(0, 10),
+            },
+            Event::PenDown,
+        ]);
+        expected.extend(strokes(1, &[p(0, 10), p(20, 10), p(0, 10)]));
+        expected.extend(vec![
+            Event::PenUp,
+            Event::Travel {
+                from: p(0, 10),
+                to: p(10, 10),
+            },
+            Event::PenDown,
+        ]);
+        assert_eq!(interpret(cmds.unwrap()), Ok(expected));
+    }
+
+    #[test]
+    fn test_arcs() {
+        let cmds = parse_commands("SP1;PA 100,0;PD;AA 0,0,-100,45;PU;AR 0,98,90;".to_string());
+        let mut expected = vec![
+            Event::SelectPen(1),
+            Event::Travel {
+                from: p(0, 0),
+                to: p(100, 0),
+            },
+            Event::PenDown,
+        ];
+        // the last chord is shorter, since 45 doesn't divide 100
+        expected.extend(strokes(
+            1,
+            &[p(100, 0), p(71, -71), p(0, -100), p(-17, -98)],
+        ));
+        expected.push(Event::PenUp);
+        let events = interpret(cmds.unwrap()).unwrap();
+        assert_eq!(&events[..expected.len()], &expected[..]);
+        // the rest is a quarter circle around (-17, 0) with the pen up, at the default chord
+        // angle of 5 degrees
+        let travels = &events[expected.len()..];
+        assert_eq!(travels.len(), 18);
+        assert_eq!(
+            travels[0],
+            Event::Travel {
+                from: p(-17, -98),
+                to: p(-8, -98),
+            }
+        );
+        assert_eq!(
+            travels[17],
+            Event::Travel {
+                from: p(81, -9),
+                to: p(81, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_scaling() {
+        let cmds = parse_commands(
+            "IP 1000,1000,2000,3000;SC -10,10,0,100;PA 0,0;PR 1,-10;CI 10,180;AA -10,95,90,90;SC 0,0,0,1;PA 0,0;SC;PA 0,0;"
+                .to_string(),
+        );
+        let travels: Vec<Point> = interpret(cmds.unwrap())
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::Travel { to, .. } | Event::Stroke { to, .. } => Some(to),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            travels,
+            vec![
+                p(1500, 1000),
+                p(1550, 800),
+                // the radius is scaled along the x axis
+                p(2050, 800),
+                p(1050, 800),
+                p(2050, 800),
+                p(1550, 800),
+                // a quarter turn around (1000, 2900)
+                p(3100, 3450),
+                // SC with an empty range is ignored
+                p(1500, 1000),
+                p(0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scaling_out_of_range() {
+        let cmds = parse_commands("SC 0,1,0,1;PA 1000000,0;".to_string());
+        assert_eq!(
+            interpret(cmds.unwrap()),
+            Err(InterpretError::ScaledOutOfRange {
+                point: p(1000000, 0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        let cmds = parse_commands(
+            "SP1;IW 0,0,100,100;PA -50,50;PD 50,50,150,150,50,150,50,120;PU 200,100;PD 0,-100;IW;PD 0,0;"
+                .to_string(),
+        );
+        assert_eq!(
+            interpret(cmds.unwrap()),
+            Ok(vec![
+                Event::SelectPen(1),
+                Event::Other(Command::InputWindow(Some((p(0, 0), p(100, 100))))),
+                Event::Travel {
+                    from: p(0, 0),
+                    to: p(-50, 50),
+                },
+                Event::PenDown,
+                // in from the left
+                Event::Travel {
+                    from: p(-50, 50),
+                    to: p(0, 50),
+                },
+                Event::Stroke {
+                    pen: 1,
+                    from: p(0, 50),
+                    to: p(50, 50),
+                },
+                // out through the corner
+                Event::Stroke {
+                    pen: 1,
+                    from: p(50, 50),
+                    to: p(100, 100),
+                },
+                Event::Travel {
+                    from: p(100, 100),
+                    to: p(150, 150),
+                },
+                // entirely outside
+                Event::Travel {
+                    from: p(150, 150),
+                    to: p(50, 150),
+                },
+                Event::Travel {
+                    from: p(50, 150),
+                    to: p(50, 120),
+                },
+                Event::PenUp,
+                Event::Travel {
+                    from: p(50, 120),
+                    to: p(200, 100),
+                },
+                Event::PenDown,
+                // only touches the corner
+                Event::Travel {
+                    from: p(200, 100),
+                    to: p(100, 0),
+                },
+                Event::Stroke {
+                    pen: 1,
+                    from: p(100, 0),
+                    to: p(100, 0),
+                },
+                Event::Travel {
+                    from: p(100, 0),
+                    to: p(0, -100),
+                },
+                Event::Other(Command::InputWindow(None)),
+                Event::PenDown,
+                Event::Stroke {
+                    pen: 1,
+                    from: p(0, -100),
+                    to: p(0, 0),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_hard_clip_limits() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hard_clip_limits(Some((p(0, 0), p(1000, 1000))));
+        let cmds = parse_commands("SP1;IW 500,-500,1500,1500;PD 1500,1000;".to_string());
+        let events: Vec<Event> = cmds
+            .unwrap()
+            .into_iter()
+            .flat_map(|cmd| interpreter.execute(cmd).unwrap())
+            .collect();
+        // only the part inside both the window and the hard clip limits is drawn
+        assert_eq!(
+            events[2..],
+            [
+                Event::PenDown,
+                Event::Travel {
+                    from: p(0, 0),
+                    to: p(500, 333),
+                },
+                Event::Stroke {
+                    pen: 1,
+                    from: p(500, 333),
+                    to: p(1000, 667),
+                },
+                Event::Travel {
+                    from: p(1000, 667),
+                    to: p(1500, 1000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clip_region() {
+        let mut interpreter = Interpreter::new();
+        // a triangle with its point at the bottom
+        interpreter.set_clip_region(Some(ClipRegion::Polygon(vec![
+            p(0, 1000),
+            p(1000, 1000),
+            p(500, 0),
+        ])));
+        let cmds = parse_commands("SP1;PA 0,500;PD 1000,500;".to_string());
+        let events: Vec<Event> = cmds
+            .unwrap()
+            .into_iter()
+            .flat_map(|cmd| interpreter.execute(cmd).unwrap())
+            .collect();
+        assert_eq!(
+            events[2..],
+            [
+                Event::PenDown,
+                Event::Travel {
+                    from: p(0, 500),
+                    to: p(250, 500),
+                },
+                Event::Stroke {
+                    pen: 1,
+                    from: p(250, 500),
+                    to: p(750, 500),
+                },
+                Event::Travel {
+                    from: p(750, 500),
+                    to: p(1000, 500),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_types() {
+        let hpgl =
+            "IP 0,0,3000,4000;LT 2,2;SP1;PD 250,0,250,100;LT 1,2;PU 0,0;PD 0,250;LT 0;PD 100,250;";
+        let travel = |from, to| Event::Travel { from, to };
+        let stroke = |from, to| Event::Stroke { pen: 1, from, to };
+        let mut interpreter = Interpreter::new();
+        interpreter.set_expand_line_types(true);
+        let mut events = vec![];
+        for cmd in parse_commands(hpgl.to_string()).unwrap() {
+            events.extend(interpreter.execute(cmd).unwrap());
+        }
+        assert_eq!(
+            &events[3..],
+            &[
+                Event::PenDown,
+                // the pattern is 100 plotter units long, 2% of the diagonal from P1 to P2
+                stroke(p(0, 0), p(50, 0)),
+                travel(p(50, 0), p(100, 0)),
+                stroke(p(100, 0), p(150, 0)),
+                travel(p(150, 0), p(200, 0)),
+                stroke(p(200, 0), p(250, 0)),
+                // the pattern carries on around the corner
+                travel(p(250, 0), p(250, 50)),
+                stroke(p(250, 50), p(250, 100)),
+                Event::Other(Command::LineType(Some((1, Some(2.0))))),
+                Event::PenUp,
+                travel(p(250, 100), p(0, 0)),
+                Event::PenDown,
+                stroke(p(0, 0), p(0, 0)),
+                travel(p(0, 0), p(0, 100)),
+                stroke(p(0, 100), p(0, 100)),
+                travel(p(0, 100), p(0, 200)),
+                stroke(p(0, 200), p(0, 200)),
+                travel(p(0, 200), p(0, 250)),
+                Event::Other(Command::LineType(Some((0, None)))),
+                Event::PenDown,
+                travel(p(0, 250), p(100, 250)),
+                stroke(p(100, 250), p(100, 250)),
+            ][..]
+        );
+
+        // without expanding them, dashed lines are drawn solid
+        let events = interpret(parse_commands(hpgl.to_string()).unwrap()).unwrap();
+        assert_eq!(events[4], stroke(p(0, 0), p(250, 0)));
+    }
+
+    #[test]
+    fn test_labels() {
+        let hpgl = "SI 0.1,0.2;LBL\r\nT\x03CP;DI 0,1;LB-\x03";
+        let travel = |from, to| Event::Travel { from, to };
+        let stroke = |from, to| Event::Stroke { pen: 0, from, to };
+        let cmds = parse_commands(hpgl.to_string()).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_expand_labels(true);
+        let mut events = vec![];
+        for cmd in cmds.clone() {
+            events.extend(interpreter.execute(cmd).unwrap());
+        }
+        assert_eq!(
+            events,
+            vec![
+                Event::Other(Command::AbsoluteCharacterSize(Some((0.1, 0.2)))),
+                // the character grid is 10 plotter units square
+                travel(p(0, 0), p(0, 80)),
+                Event::PenDown,
+                stroke(p(0, 80), p(0, 0)),
+                stroke(p(0, 0), p(40, 0)),
+                // a carriage return and line feed
+                Event::PenUp,
+                travel(p(40, 0), p(0, -80)),
+                Event::PenDown,
+                stroke(p(0, -80), p(40, -80)),
+                Event::PenUp,
+                travel(p(40, -80), p(20, -80)),
+                Event::PenDown,
+                stroke(p(20, -80), p(20, -160)),
+                Event::PenUp,
+                travel(p(20, -160), p(60, -160)),
+                Event::Other(Command::Label("L\r\nT".to_string())),
+                travel(p(60, -160), p(0, -320)),
+                Event::Other(Command::CharacterPlot(None)),
+                Event::Other(Command::AbsoluteDirection(Some((0.0, 1.0)))),
+                // straight up
+                travel(p(0, -320), p(-40, -320)),
+                Event::PenDown,
+                stroke(p(-40, -320), p(-40, -280)),
+                Event::PenUp,
+                travel(p(-40, -280), p(0, -260)),
+                Event::Other(Command::Label("-".to_string())),
+            ]
+        );
+
+        // without expanding them, labels only move the pen
+        let mut interpreter = Interpreter::new();
+        for cmd in cmds {
+            let events = interpreter.execute(cmd).unwrap();
+            assert!(events.iter().all(|e| matches!(e, Event::Other(_))));
+        }
+        assert_eq!(interpreter.state().position, p(0, -260));
+    }
+
+    #[test]
+    fn test_arc_out_of_range() {
+        let cmds = vec![
+            Command::PlotAbsolute(vec![p(i32::MAX - 10, 0)]),
+            Command::Circle(100, None),
+        ];
+        assert_eq!(
+            interpret(cmds),
+            Err(InterpretError::ArcOutOfRange {
+                center: p(i32::MAX - 10, 0),
+                radius: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_state() {
+        let mut interpreter = Interpreter::new();
+        let cmds = parse_commands(
+            "SP1;PR;PD 5,5;VS 10;IP 100,100;SC 0,10,0,10;IW 0,0,500,500;".to_string(),
+        )
+        .unwrap();
+        for cmd in cmds {
+            interpreter.execute(cmd).unwrap();
+        }
+        assert_eq!(
+            interpreter.state(),
+            &State {
+                position: p(5, 5),
+                absolute: false,
+                pen_down: true,
+                pen: 1,
+                p1: p(100, 100),
+                p2: p(10100, 7300),
+                scaling: Some(Scaling {
+                    x_min: 0,
+                    x_max: 10,
+                    y_min: 0,
+                    y_max: 10
+                }),
+                window: Some((p(0, 0), p(500, 500))),
+                velocity: Some(10.0),
+                line_type: None,
+                lettering: Lettering::default(),
+            }
+        );
+
+        interpreter.execute(Command::Default).unwrap();
+        assert_eq!(
+            interpreter.state(),
+            &State {
+                position: p(5, 5),
+                pen_down: true,
+                pen: 1,
+                p1: p(100, 100),
+                p2: p(10100, 7300),
+                ..State::default()
+            }
+        );
+
+        interpreter.execute(Command::Initialize).unwrap();
+        assert_eq!(
+            interpreter.state(),
+            &State {
+                position: p(5, 5),
+                pen: 1,
+                ..State::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::command::{CharacterStroke, Command};
+use crate::{Point, UNITS_PER_CM};
+
+/// The size of the character body in grid units, which `UC` characters and `glyph` are drawn on.
+/// A character space is 1.5 times the width of the body, and a line is twice its height.
+pub const GRID_WIDTH: f64 = 4.0;
+pub const GRID_HEIGHT: f64 = 8.0;
+
+/// The size of label characters, set by `SI` and `SR`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CharacterSize {
+    /// The width and height of the character body, in cm.
+    Absolute(f64, f64),
+    /// The width and height of the character body, in percent of P2 - P1.
+    Relative(f64, f64),
+}
+
+/// The direction labels are drawn in, set by `DI` and `DR`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    /// The run and rise, in plotter units.
+    Absolute(f64, f64),
+    /// The run and rise, in percent of P2 - P1.
+    Relative(f64, f64),
+}
+
+/// Everything that affects how labels are drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lettering {
+    pub size: CharacterSize,
+    /// The tangent of the angle characters lean right by, set by `SL`.
+    pub slant: f64,
+    pub direction: Direction,
+    /// The character sets designated by `CS` and `CA`.
+    pub standard_set: u8,
+    pub alternate_set: u8,
+    /// Whether the alternate set is selected, by `SA` or a shift out character in a label.
+    pub alternate_selected: bool,
+}
+
+impl Default for Lettering {
+    fn default() -> Lettering {
+        Lettering {
+            size: CharacterSize::Relative(0.75, 1.5),
+            slant: 0.0,
+            direction: Direction::Absolute(1.0, 0.0),
+            standard_set: 0,
+            alternate_set: 0,
+            alternate_selected: false,
+        }
+    }
+}
+
+impl Lettering {
+    /// Updates the lettering for one of the commands that changes it, ignoring anything else.
+    pub(crate) fn apply(&mut self, cmd: &Command) {
+        match cmd {
+            Command::AbsoluteCharacterSize(size) => {
+                let (width, height) = size.unwrap_or((0.19, 0.27));
+                self.size = CharacterSize::Absolute(width, height);
+            }
+            Command::RelativeCharacterSize(size) => {
+                let (width, height) = size.unwrap_or((0.75, 1.5));
+                self.size = CharacterSize::Relative(width, height);
+            }
+            Command::CharacterSlant(slant) => self.slant = slant.unwrap_or(0.0),
+            // like the plotter, ignore directions that don't point anywhere
+            Command::AbsoluteDirection(Some((run, rise)))
+            | Command::RelativeDirection(Some((run, rise)))
+                if *run == 0.0 && *rise == 0.0 => {}
+            Command::AbsoluteDirection(direction) => {
+                let (run, rise) = direction.unwrap_or((1.0, 0.0));
+                self.direction = Direction::Absolute(run, rise);
+            }
+            Command::RelativeDirection(direction) => {
+                let (run, rise) = direction.unwrap_or((1.0, 0.0));
+                self.direction = Direction::Relative(run, rise);
+            }
+            Command::DesignateStandardSet(set) => self.standard_set = set.unwrap_or(0),
+            Command::DesignateAlternateSet(set) => self.alternate_set = set.unwrap_or(0),
+            Command::SelectStandardSet => self.alternate_selected = false,
+            Command::SelectAlternateSet => self.alternate_selected = true,
+            _ => {}
+        }
+    }
+
+    /// Works out where characters go, given the scaling points.
+    pub(crate) fn layout(&self, p1: Point, p2: Point) -> Layout {
+        let p2_p1 = (
+            f64::from(p2.x) - f64::from(p1.x),
+            f64::from(p2.y) - f64::from(p1.y),
+        );
+        let (width, height) = match self.size {
+            CharacterSize::Absolute(width, height) => (width * UNITS_PER_CM, height * UNITS_PER_CM),
+            CharacterSize::Relative(width, height) => {
+                (width / 100.0 * p2_p1.0, height / 100.0 * p2_p1.1)
+            }
+        };
+        let (run, rise) = match self.direction {
+            Direction::Absolute(run, rise) => (run, rise),
+            Direction::Relative(run, rise) => (run / 100.0 * p2_p1.0, rise / 100.0 * p2_p1.1),
+        };
+        let length = run.hypot(rise);
+        // a relative direction can still end up pointing nowhere if P1 and P2 line up
+        let (run, rise) = if length == 0.0 {
+            (1.0, 0.0)
+        } else {
+            (run / length, rise / length)
+        };
+        let unit_height = height / GRID_HEIGHT;
+        Layout {
+            across: (run * width / GRID_WIDTH, rise * width / GRID_WIDTH),
+            up: (
+                (-rise + run * self.slant) * unit_height,
+                (run + rise * self.slant) * unit_height,
+            ),
+            line: (-rise * height * 2.0, run * height * 2.0),
+        }
+    }
+}
+
+/// Where a character grid ends up on the page, in plotter units.
+pub(crate) struct Layout {
+    /// One grid unit along the label.
+    across: (f64, f64),
+    /// One grid unit up the character, including the slant.
+    up: (f64, f64),
+    /// One line up, without the slant.
+    line: (f64, f64),
+}
+
+impl Layout {
+    /// A point on a character's grid, for a character whose lower left corner is at `origin`.
+    pub(crate) fn point(&self, origin: (f64, f64), x: f64, y: f64) -> (f64, f64) {
+        (
+            origin.0 + self.across.0 * x + self.up.0 * y,
+            origin.1 + self.across.1 * x + self.up.1 * y,
+        )
+    }
+
+    /// Moves by a number of character spaces and lines, as with `CP`.
+    pub(crate) fn moved(&self, origin: (f64, f64), spaces: f64, lines: f64) -> (f64, f64) {
+        let space = GRID_WIDTH * 1.5 * spaces;
+        (
+            origin.0 + self.across.0 * space + self.line.0 * lines,
+            origin.1 + self.across.1 * space + self.line.1 * lines,
+        )
+    }
+
+    /// Moves back along the label to the carriage return point, staying on the same line.
+    pub(crate) fn carriage_return(&self, origin: (f64, f64), cr: (f64, f64)) -> (f64, f64) {
+        let length_squared = self.line.0 * self.line.0 + self.line.1 * self.line.1;
+        if length_squared == 0.0 {
+            return cr;
+        }
+        let lines =
+            ((origin.0 - cr.0) * self.line.0 + (origin.1 - cr.1) * self.line.1) / length_squared;
+        (cr.0 + self.line.0 * lines, cr.1 + self.line.1 * lines)
+    }
+}
+
+/// The lines a `UC` character is made of, in grid units. The pen starts up, at the lower left
+/// corner of the character.
+pub(crate) fn user_defined_character(strokes: &[CharacterStroke]) -> Vec<Vec<(f64, f64)>> {
+    let mut lines = vec![];
+    let mut line: Option<Vec<(f64, f64)>> = None;
+    let mut position = (0.0, 0.0);
+    for stroke in strokes {
+        match stroke {
+            CharacterStroke::PenDown => {
+                if line.is_none() {
+                    line = Some(vec![position]);
+                }
+            }
+            CharacterStroke::PenUp => lines.extend(line.take()),
+            CharacterStroke::Move(x, y) => {
+                position = (position.0 + f64::from(*x), position.1 + f64::from(*y));
+                if let Some(line) = &mut line {
+                    line.push(position);
+                }
+            }
+        }
+    }
+    lines.extend(line);
+    lines
+}
+
+/// The lines that make up a character from the plotter's character set 0 (ascii), in grid units,
+/// or `None` if it isn't a printable ascii character. A line with only one point is a dot.
+///
+/// The glyphs follow the shapes and proportions of the plotter's own, but aren't traced from
+/// them, so they won't line up exactly. The body of capitals and digits fills the grid, lowercase
+/// letters are 5 units tall, and descenders go 3 units below the baseline.
+pub fn glyph(c: char) -> Option<Vec<Vec<(f64, f64)>>> {
+    let index = (c as usize).checked_sub(0x20)?;
+    let glyph = GLYPHS.get(index)?;
+    Some(
+        glyph
+            .split(';')
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|point| {
+                        let mut coords = point.split(',').map(|c| c.parse().unwrap());
+                        (coords.next().unwrap(), coords.next().unwrap())
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Character set 0, from space to tilde. Each glyph is a list of lines separated by `;`, and each
+/// line is a list of `x,y` points on the character grid.
+const GLYPHS: [&str; 95] = [
+    // space ! " # $ % & '
+    "",
+    "2,8 2,2; 2,0",
+    "1,8 1,6; 3,8 3,6",
+    "1,0 1,8; 3,0 3,8; 0,2.5 4,2.5; 0,5.5 4,5.5",
+    "4,7 3,8 1,8 0,7 0,5 1,4 3,4 4,3 4,1 3,0 1,0 0,1; 2,9 2,-1",
+    "0,0 4,8; 1,8 0,7 1,6 2,7 1,8; 3,2 2,1 3,0 4,1 3,2",
+    "4,0 1,6 1,7 2,8 3,7 3,6 0,3 0,1 1,0 2,0 4,3",
+    "2,8 2,6",
+    // ( ) * + , - . /
+    "3,9 2,8 1,6 1,2 2,0 3,-1",
+    "1,9 2,8 3,6 3,2 2,0 1,-1",
+    "2,6.5 2,1.5; 0,5.5 4,2.5; 0,2.5 4,5.5",
+    "2,6 2,2; 0,4 4,4",
+    "2,0.5 2,0 1,-1.5",
+    "0,4 4,4",
+    "2,0",
+    "0,0 4,8",
+    // 0 - 9
+    "1,0 0,1 0,7 1,8 3,8 4,7 4,1 3,0 1,0; 0,1 4,7",
+    "1,6 2,8 2,0; 1,0 3,0",
+    "0,7 1,8 3,8 4,7 4,5 0,1 0,0 4,0",
+    "0,7 1,8 3,8 4,7 4,5 3,4 1,4; 3,4 4,3 4,1 3,0 1,0 0,1",
+    "3,0 3,8 0,2 4,2",
+    "4,8 0,8 0,4 3,4 4,3 4,1 3,0 1,0 0,1",
+    "4,7 3,8 1,8 0,7 0,1 1,0 3,0 4,1 4,3 3,4 0,4",
+    "0,8 4,8 1,0",
+    "1,4 0,5 0,7 1,8 3,8 4,7 4,5 3,4 1,4 0,3 0,1 1,0 3,0 4,1 4,3 3,4",
+    "4,4 1,4 0,5 0,7 1,8 3,8 4,7 4,1 3,0 1,0 0,1",
+    // : ; < = > ? @
+    "2,5; 2,1",
+    "2,5; 2,1 1,-1",
+    "4,7 0,4 4,1",
+    "0,5 4,5; 0,3 4,3",
+    "0,7 4,4 0,1",
+    "0,7 1,8 3,8 4,7 4,5 2,3 2,2; 2,0",
+    "3,3 1,3 1,5 3,5 3,2 4,2 4,7 3,8 1,8 0,7 0,1 1,0 4,0",
+    // A - Z
+    "0,0 0,6 2,8 4,6 4,0; 0,4 4,4",
+    "0,0 0,8 3,8 4,7 4,5 3,4 0,4; 3,4 4,3 4,1 3,0 0,0",
+    "4,7 3,8 1,8 0,7 0,1 1,0 3,0 4,1",
+    "0,0 0,8 2,8 4,6 4,2 2,0 0,0",
+    "4,0 0,0 0,8 4,8; 0,4 3,4",
+    "0,0 0,8 4,8; 0,4 3,4",
+    "4,7 3,8 1,8 0,7 0,1 1,0 3,0 4,1 4,4 2,4",
+    "0,0 0,8; 4,0 4,8; 0,4 4,4",
+    "1,0 3,0; 2,0 2,8; 1,8 3,8",
+    "0,1 1,0 2,0 3,1 3,8; 2,8 4,8",
+    "0,0 0,8; 4,8 0,3; 1.2,4.5 4,0",
+    "0,8 0,0 4,0",
+    "0,0 0,8 2,4 4,8 4,0",
+    "0,0 0,8 4,0 4,8",
+    "1,0 0,1 0,7 1,8 3,8 4,7 4,1 3,0 1,0",
+    "0,0 0,8 3,8 4,7 4,5 3,4 0,4",
+    "1,0 0,1 0,7 1,8 3,8 4,7 4,1 3,0 1,0; 2,2 4,0",
+    "0,0 0,8 3,8 4,7 4,5 3,4 0,4; 2,4 4,0",
+    "4,7 3,8 1,8 0,7 0,5 1,4 3,4 4,3 4,1 3,0 1,0 0,1",
+    "0,8 4,8; 2,8 2,0",
+    "0,8 0,1 1,0 3,0 4,1 4,8",
+    "0,8 2,0 4,8",
+    "0,8 1,0 2,4 3,0 4,8",
+    "0,0 4,8; 0,8 4,0",
+    "0,8 2,4 4,8; 2,4 2,0",
+    "0,8 4,8 0,0 4,0",
+    // [ \ ] ^ _ `
+    "3,9 1,9 1,-1 3,-1",
+    "0,8 4,0",
+    "1,9 3,9 3,-1 1,-1",
+    "0,5 2,8 4,5",
+    "0,-1 4,-1",
+    "1,8 3,6",
+    // a - z
+    "1,5 3,5 4,4 4,0; 4,3 1,3 0,2 0,1 1,0 3,0 4,1",
+    "0,8 0,0; 0,1 1,0 3,0 4,1 4,4 3,5 1,5 0,4",
+    "4,4 3,5 1,5 0,4 0,1 1,0 3,0 4,1",
+    "4,8 4,0; 4,1 3,0 1,0 0,1 0,4 1,5 3,5 4,4",
+    "0,3 4,3 4,4 3,5 1,5 0,4 0,1 1,0 3,0 4,1",
+    "1,0 1,7 2,8 3,8 4,7; 0,5 3,5",
+    "4,5 4,-2 3,-3 1,-3 0,-2; 4,1 3,0 1,0 0,1 0,4 1,5 3,5 4,4",
+    "0,8 0,0; 0,4 1,5 3,5 4,4 4,0",
+    "2,5 2,0; 2,7",
+    "3,5 3,-2 2,-3 1,-3 0,-2; 3,7",
+    "0,8 0,0; 4,5 0,2; 1,2.75 4,0",
+    "1,8 2,8 2,0; 1,0 3,0",
+    "0,5 0,0; 0,4 1,5 2,4 2,0; 2,4 3,5 4,4 4,0",
+    "0,5 0,0; 0,4 1,5 3,5 4,4 4,0",
+    "1,0 0,1 0,4 1,5 3,5 4,4 4,1 3,0 1,0",
+    "0,5 0,-3; 0,4 1,5 3,5 4,4 4,1 3,0 1,0 0,1",
+    "4,5 4,-3; 4,4 3,5 1,5 0,4 0,1 1,0 3,0 4,1",
+    "0,5 0,0; 0,3 2,5 4,5",
+    "4,4 3,5 1,5 0,4 0,3.5 1,2.5 3,2.5 4,1.5 4,1 3,0 1,0 0,1",
+    "1,8 1,1 2,0 3,0 4,1; 0,5 3,5",
+    "0,5 0,1 1,0 3,0 4,1; 4,5 4,0",
+    "0,5 2,0 4,5",
+    "0,5 1,0 2,3 3,0 4,5",
+    "0,0 4,5; 0,5 4,0",
+    "0,5 2.125,0; 4,5 1,-3 0,-3",
+    "0,5 4,5 0,0 4,0",
+    // { | } ~
+    "3,9 2,8 2,5 1,4 2,3 2,0 3,-1",
+    "2,9 2,-1",
+    "1,9 2,8 2,5 3,4 2,3 2,0 1,-1",
+    "0,6 1,7 3,5 4,6",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glyphs() {
+        for c in (0x20u8..0x7f).map(char::from) {
+            let glyph = glyph(c).unwrap();
+            assert_eq!(glyph.is_empty(), c == ' ', "{:?}", c);
+            for (x, y) in glyph.into_iter().flatten() {
+                assert!((0.0..=GRID_WIDTH).contains(&x), "{:?}", c);
+                assert!((-3.0..=GRID_HEIGHT + 1.0).contains(&y), "{:?}", c);
+            }
+        }
+        assert_eq!(glyph('\n'), None);
+        assert_eq!(glyph('é'), None);
+        assert_eq!(
+            glyph('L'),
+            Some(vec![vec![(0.0, 8.0), (0.0, 0.0), (4.0, 0.0)]])
+        );
+    }
+
+    #[test]
+    fn test_user_defined_character() {
+        let strokes = [
+            CharacterStroke::Move(1, 1),
+            CharacterStroke::PenDown,
+            CharacterStroke::Move(2, 0),
+            CharacterStroke::Move(0, 2),
+            CharacterStroke::PenUp,
+            CharacterStroke::Move(-2, 0),
+            CharacterStroke::PenDown,
+        ];
+        assert_eq!(
+            user_defined_character(&strokes),
+            vec![vec![(1.0, 1.0), (3.0, 1.0), (3.0, 3.0)], vec![(1.0, 3.0)]]
+        );
+    }
+}
@@ -1,210 +1,384 @@
+use std::io;
+
+mod clip;
+mod command;
+pub mod device;
+mod drawing;
+mod interpret;
+mod label;
+mod lint;
+mod parse;
+mod simplify;
+mod stats;
+pub mod transform;
+mod write;
+
+pub use clip::ClipRegion;
+pub use command::{CharacterStroke, Command, InputMask, Scaling, ETX};
+pub use drawing::{Drawing, DrawingBuilder, Pen, Polyline};
+pub use interpret::{
+    interpret, Event, InterpretError, Interpreter, State, DEFAULT_CHORD_ANGLE, DEFAULT_P1,
+    DEFAULT_P2, DEFAULT_PATTERN_LENGTH,
+};
+pub use label::{glyph, CharacterSize, Direction, Lettering, GRID_HEIGHT, GRID_WIDTH};
+pub use lint::{Lint, LintKind, Linter, Severity};
+pub use parse::{
+    parse_commands, read_commands, CommandReader, ParseError, ParseErrorKind, Span, Spanned,
+};
+pub use simplify::simplify;
+pub use stats::{stats, Stats, StatsCollector};
+pub use write::{serialize, CommandWriter, Style};
+
+/// Plotter units per centimeter.
+pub const UNITS_PER_CM: f64 = 400.0;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Command {
-    PenUp(Vec<Point>),
-    PenDown(Vec<Point>),
-    PlotAbsolute(Vec<Point>),
-    PlotRelative(Vec<Point>),
-    SelectPen(u8),
-    Initialize,
-}
-
-pub fn parse_commands(cmd_str: String) -> Result<Vec<Command>, Vec<String>> {
-    let cmds = cmd_str
-        .split(";")
-        .map(|cmd| cmd.trim())
-        .filter(|cmd| cmd.len() != 0);
-    let maybe_parsed_cmds = cmds.map(|cmd| parse_command(cmd.to_string()));
-    let any_failures = maybe_parsed_cmds
-        .clone()
-        .fold(false, |flag, curr| flag || curr.is_err());
-    if any_failures {
-        Err(maybe_parsed_cmds
-            .filter_map(|cmd| match cmd {
-                Ok(_) => None,
-                Err(e) => Some(e),
-            })
-            .collect())
-    } else {
-        Ok(maybe_parsed_cmds
-            .filter_map(|cmd| match cmd {
-                Ok(c) => Some(c),
-                Err(_) => None,
-            })
-            .collect())
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum CanonicalCommand {
     PenUp,
     PenDown,
     PlotAbsolute(Point),
     SelectPen(u8),
     Initialize,
+    /// Any other command (apart from `SC` and `IW`), which is passed through untouched.
+    Other(Command),
+}
+
+/// Optional extra steps for `canonicalize_with_options`.
+#[derive(Debug, Default, Clone)]
+pub struct CanonicalizeOptions {
+    /// Break dashed and dotted lines (see `LT`) up into separate strokes, and drop the `LT`
+    /// commands, for devices that can't draw them themselves.
+    pub expand_line_types: bool,
+    /// Draw labels (`LB` and `UC`) as strokes, using the plotter's character set 0, and drop the
+    /// `LB`, `UC` and `CP` commands, for devices that don't have a font of their own.
+    pub expand_labels: bool,
+    /// Only draw what's inside the area the pen can reach (see `device::Model`), as the plotter
+    /// would. Lines that go outside of it are broken up like lines that go outside of the `IW`
+    /// window.
+    pub hard_clip_limits: Option<(Point, Point)>,
+    /// Only draw what's inside of this region, breaking lines up the same way as for the hard
+    /// clip limits.
+    pub clip_region: Option<ClipRegion>,
 }
 
 /// Canonicalize a list of commands:
 /// * PU and PD do not have coords associated
-/// * All movements are absolute
-pub fn canonicalize(cmds: Vec<Command>) -> Vec<CanonicalCommand> {
+/// * All movements are absolute, in plotter units
+/// * SC and IW are dropped, since every point has already been scaled, and every line clipped
+pub fn canonicalize(cmds: Vec<Command>) -> Result<Vec<CanonicalCommand>, InterpretError> {
+    canonicalize_with_options(cmds, &CanonicalizeOptions::default())
+}
+
+/// Canonicalize a list of commands, as with `canonicalize`, doing whatever else `options` asks
+/// for.
+pub fn canonicalize_with_options(
+    cmds: Vec<Command>,
+    options: &CanonicalizeOptions,
+) -> Result<Vec<CanonicalCommand>, InterpretError> {
     let mut out = vec![];
-    let mut absolute_coords = true;
+    let mut interpreter = Interpreter::new();
+    interpreter.set_expand_line_types(options.expand_line_types);
+    interpreter.set_expand_labels(options.expand_labels);
+    interpreter.set_hard_clip_limits(options.hard_clip_limits);
+    interpreter.set_clip_region(options.clip_region.clone());
+    // whether the pen is down in what we've output so far, which isn't the same as the
+    // interpreter's pen state when a line goes outside of the window
+    let mut drawing = false;
     for cmd in cmds {
-        match cmd {
-            Command::PenUp(points) => {
-                out.push(CanonicalCommand::PenUp);
-                if absolute_coords {
-                    if let Some(point) = points.last() {
-                        out.push(CanonicalCommand::PlotAbsolute(*point));
+        // with the pen up, only the point we end up at matters
+        let pen_up = matches!(cmd, Command::PenUp(_));
+        // where the pen is as of each event
+        let mut position = interpreter.state().position;
+        let events = interpreter.execute(cmd)?;
+        let last_move = events
+            .iter()
+            .rposition(|e| matches!(e, Event::Travel { .. } | Event::Stroke { .. }));
+        for (i, event) in events.into_iter().enumerate() {
+            match event {
+                Event::Travel { to, .. } | Event::Stroke { to, .. }
+                    if pen_up && Some(i) != last_move =>
+                {
+                    position = to;
+                }
+                Event::Travel { to, .. } => {
+                    position = to;
+                    if drawing {
+                        out.push(CanonicalCommand::PenUp);
+                        drawing = false;
                     }
-                } else {
-                    panic!("can't handle relative coords :(");
+                    out.push(CanonicalCommand::PlotAbsolute(to));
                 }
-            },
-            Command::PenDown(points) => {
-                out.push(CanonicalCommand::PenDown);
-                if absolute_coords {
-                    for point in points {
-                        out.push(CanonicalCommand::PlotAbsolute(point));
+                Event::Stroke { to, .. } => {
+                    position = to;
+                    if !drawing {
+                        out.push(CanonicalCommand::PenDown);
+                        drawing = true;
                     }
-                } else {
-                    panic!("can't handle relative coords :(");
+                    out.push(CanonicalCommand::PlotAbsolute(to));
                 }
-            },
-            Command::PlotAbsolute(points) => {
-                for point in points {
-                    out.push(CanonicalCommand::PlotAbsolute(point));
+                Event::PenUp => {
+                    out.push(CanonicalCommand::PenUp);
+                    drawing = false;
                 }
-            },
-            Command::PlotRelative(_) => {
-                panic!("can't handle relative coords :(");
-            },
-            Command::SelectPen(pen) => {
-                out.push(CanonicalCommand::SelectPen(pen));
-            },
-            Command::Initialize => {
-                out.push(CanonicalCommand::Initialize);
+                // outside of the window the plotter doesn't put the pen down until a line comes
+                // back inside, which the `Stroke` arm takes care of
+                Event::PenDown if interpreter.draws_at(position) => {
+                    out.push(CanonicalCommand::PenDown);
+                    drawing = true;
+                }
+                Event::PenDown => {}
+                Event::SelectPen(pen) => out.push(CanonicalCommand::SelectPen(pen)),
+                Event::Initialize => {
+                    out.push(CanonicalCommand::Initialize);
+                    drawing = false;
+                }
+                Event::Other(Command::Scale(_)) | Event::Other(Command::InputWindow(_)) => {}
+                Event::Other(Command::LineType(_)) if options.expand_line_types => {}
+                Event::Other(Command::Label(_))
+                | Event::Other(Command::UserDefinedCharacter(_))
+                | Event::Other(Command::CharacterPlot(_))
+                    if options.expand_labels => {}
+                Event::Other(cmd) => out.push(CanonicalCommand::Other(cmd)),
             }
         }
     }
-    out
+    Ok(out)
 }
 
-pub fn canonical_commands_to_string(cmds: Vec<CanonicalCommand>) -> String {
-    let mut out = String::new();
-    for cmd in cmds{
-        match cmd{
-            CanonicalCommand::PenUp => out.push_str("PU;\n"),
-            CanonicalCommand::PenDown => out.push_str("PD;\n"),
-            CanonicalCommand::PlotAbsolute(point) => out.push_str(&format!("PA{},{};\n", point.x, point.y)),
-            CanonicalCommand::SelectPen(pen) => out.push_str(&format!("SP{};\n", pen)),
-            CanonicalCommand::Initialize => out.push_str("IN;\n"),
+impl From<CanonicalCommand> for Command {
+    fn from(cmd: CanonicalCommand) -> Command {
+        match cmd {
+            CanonicalCommand::PenUp => Command::PenUp(vec![]),
+            CanonicalCommand::PenDown => Command::PenDown(vec![]),
+            CanonicalCommand::PlotAbsolute(point) => Command::PlotAbsolute(vec![point]),
+            CanonicalCommand::SelectPen(pen) => Command::SelectPen(pen),
+            CanonicalCommand::Initialize => Command::Initialize,
+            CanonicalCommand::Other(cmd) => cmd,
         }
     }
-    out
 }
 
-fn parse_command(cmd: String) -> Result<Command, String> {
-    if cmd.len() < 2 {
-        return Err(cmd);
-    }
-    let cmd_type: String = cmd[0..2].to_string();
-    let mut points: Vec<Point> = vec![];
-    if cmd.len() > 2 && cmd_type != "SP" {
-        let coords_part: String = cmd[2..].to_string();
-        let coords: Vec<_> = coords_part.split(",").collect();
-        if coords.len() % 2 != 0 {
-            return Err(cmd);
-        }
-        for i in 0..coords.len() / 2 {
-            points.push(Point {
-                x: coords[i * 2].trim().parse().map_err(|_| cmd.clone())?,
-                y: coords[i * 2 + 1].trim().parse().map_err(|_| cmd.clone())?,
-            });
-        }
-    }
-    if cmd_type == "PU" {
-        Ok(Command::PenUp(points))
-    } else if cmd_type == "PD" {
-        Ok(Command::PenDown(points))
-    } else if cmd_type == "PA" {
-        Ok(Command::PlotAbsolute(points))
-    } else if cmd_type == "PR" {
-        Ok(Command::PlotRelative(points))
-    } else if cmd_type == "SP" {
-        let pen = cmd[2..].to_string().trim().parse().unwrap();
-        Ok(Command::SelectPen(pen))
-    } else if cmd_type == "IN" {
-        Ok(Command::Initialize)
-    } else {
-        Err(cmd)
-    }
+/// Writes canonical commands as HPGL, one per line. This only fails if one of the passed through
+/// commands can't be written (see `CommandWriter`).
+pub fn canonical_commands_to_string(cmds: Vec<CanonicalCommand>) -> io::Result<String> {
+    let cmds: Vec<Command> = cmds.into_iter().map(Command::from).collect();
+    serialize(&cmds, Style::OnePerLine)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    fn check_parse_command(cmd: &str, expected: Option<Command>) {
-        let parsed = parse_command(cmd.to_string());
-        match expected {
-            Some(cmd) => assert_eq!(parsed, Ok(cmd)),
-            None => assert_eq!(parsed, Err(cmd.to_string())),
-        }
-    }
-
     #[test]
-    fn test_parse_multiple_commands() {
-        assert_eq!(
-            parse_commands("  PU  ; PD  ; ".to_string()),
-            Ok(vec![Command::PenUp(vec![]), Command::PenDown(vec![])])
-        );
-        assert_eq!(parse_commands("".to_string()), Ok(vec![]));
-        assert_eq!(parse_commands("\n".to_string()), Ok(vec![]));
-        assert_eq!(parse_commands("  ".to_string()), Ok(vec![]));
+    fn test_canonicalize() {
+        let cmds = canonicalize(
+            parse_commands(include_str!("../testdata/canonicalize.hpgl").to_string()).unwrap(),
+        )
+        .unwrap();
         assert_eq!(
-            parse_commands("  PU  ; command_not_valid ; PD  ; ".to_string()),
-            Err(vec!["command_not_valid".to_string()])
+            cmds,
+            vec![
+                CanonicalCommand::SelectPen(1),
+                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 1000 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 2000 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 2000, y: 2000 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 2000, y: 1000 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 1000 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 0, y: 0 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 5000, y: 5000 }),
+                CanonicalCommand::PenUp,
+            ]
         );
     }
 
     #[test]
-    fn test_parser() {
-        check_parse_command("", None);
-        check_parse_command("a", None);
-        check_parse_command("command_not_valid", None);
-        check_parse_command("PU ", None);
-        check_parse_command(" PU", None);
-        check_parse_command("PA foo, bar", None);
-        check_parse_command("PU", Some(Command::PenUp(vec![])));
-        // TODO: check commands that take points
-    }
-
-    #[test]
-    fn test_canonicalize() {
-        let cmds = canonicalize(parse_commands(include_str!("../testdata/canonicalize.hpgl").to_string()).unwrap());
+    fn test_canonicalize_relative() {
+        let cmds = canonicalize(
+            parse_commands(include_str!("../testdata/canonicalize_relative.hpgl").to_string())
+                .unwrap(),
+        )
+        .unwrap();
         assert_eq!(
             cmds,
             vec![
                 CanonicalCommand::SelectPen(1),
+                CanonicalCommand::PenUp,
                 CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 1000 }),
                 CanonicalCommand::PenDown,
-                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 2000 }),
-                CanonicalCommand::PlotAbsolute(Point { x: 2000, y: 2000 }),
                 CanonicalCommand::PlotAbsolute(Point { x: 2000, y: 1000 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 2000, y: 2000 }),
                 CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 1000 }),
                 CanonicalCommand::PenUp,
                 CanonicalCommand::PlotAbsolute(Point { x: 0, y: 0 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 500, y: 500 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 500 }),
                 CanonicalCommand::PenDown,
-                CanonicalCommand::PlotAbsolute(Point { x: 5000, y: 5000 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 1000, y: 1000 }),
                 CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 500, y: 500 }),
             ]
         );
     }
+
+    #[test]
+    fn test_canonicalize_scaled() {
+        let cmds = parse_commands("IP 0,0,1000,1000;SC 0,10,0,10;PD 1,2;PR 1,1;".to_string());
+        assert_eq!(
+            canonicalize(cmds.unwrap()),
+            Ok(vec![
+                CanonicalCommand::Other(Command::InputP1P2(Some((
+                    Point { x: 0, y: 0 },
+                    Some(Point { x: 1000, y: 1000 })
+                )))),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 100, y: 200 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 200, y: 300 }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_window() {
+        let cmds =
+            parse_commands("IW 0,0,100,100;PA -50,50;PD 50,50,50,150,-50,150;PU;".to_string());
+        assert_eq!(
+            canonicalize(cmds.unwrap()),
+            // the pen isn't put down at (-50, 50), outside of the window, which would leave a dot
+            Ok(vec![
+                CanonicalCommand::PlotAbsolute(Point { x: -50, y: 50 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 0, y: 50 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 50, y: 50 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 50, y: 100 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 50, y: 150 }),
+                CanonicalCommand::PlotAbsolute(Point { x: -50, y: 150 }),
+                CanonicalCommand::PenUp,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_line_types() {
+        let cmds = parse_commands("IP 0,0,3000,4000;LT 3,2;PD 200,0;".to_string()).unwrap();
+        let options = CanonicalizeOptions {
+            expand_line_types: true,
+            ..CanonicalizeOptions::default()
+        };
+        assert_eq!(
+            canonicalize_with_options(cmds, &options),
+            Ok(vec![
+                CanonicalCommand::Other(Command::InputP1P2(Some((
+                    Point { x: 0, y: 0 },
+                    Some(Point { x: 3000, y: 4000 })
+                )))),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 70, y: 0 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 100, y: 0 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 170, y: 0 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 200, y: 0 }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_labels() {
+        let cmds = parse_commands("SI 0.1,0.2;PD;LB.\x03CP 1,0;PU;".to_string()).unwrap();
+        let options = CanonicalizeOptions {
+            expand_labels: true,
+            ..CanonicalizeOptions::default()
+        };
+        assert_eq!(
+            canonicalize_with_options(cmds, &options),
+            Ok(vec![
+                CanonicalCommand::Other(Command::AbsoluteCharacterSize(Some((0.1, 0.2)))),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 20, y: 0 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 20, y: 0 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 60, y: 0 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 120, y: 0 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PenUp,
+            ])
+        );
+
+        // with a window that only the start of the label is inside, the pen is never put down
+        // outside of it, not even where it goes back down after the label and the CP
+        let cmds =
+            parse_commands("IW 0,0,10,10;SI 0.1,0.2;PD;LB.\x03CP 1,0;PU;".to_string()).unwrap();
+        assert_eq!(
+            canonicalize_with_options(cmds, &options),
+            Ok(vec![
+                CanonicalCommand::Other(Command::AbsoluteCharacterSize(Some((0.1, 0.2)))),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 20, y: 0 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 20, y: 0 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 60, y: 0 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 120, y: 0 }),
+                CanonicalCommand::PenUp,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_clip_region() {
+        let cmds = parse_commands("SP1;PA 500,500;PD;PA 50,50;PU;PA 0,200;PD;PU;".to_string());
+        let options = CanonicalizeOptions {
+            clip_region: Some(ClipRegion::Rectangle(
+                Point { x: 0, y: 0 },
+                Point { x: 100, y: 100 },
+            )),
+            ..CanonicalizeOptions::default()
+        };
+        // the pen only goes down once the line comes inside of the region, and not at all for
+        // the dot outside of it
+        assert_eq!(
+            canonicalize_with_options(cmds.unwrap(), &options),
+            Ok(vec![
+                CanonicalCommand::SelectPen(1),
+                CanonicalCommand::PlotAbsolute(Point { x: 500, y: 500 }),
+                CanonicalCommand::PlotAbsolute(Point { x: 100, y: 100 }),
+                CanonicalCommand::PenDown,
+                CanonicalCommand::PlotAbsolute(Point { x: 50, y: 50 }),
+                CanonicalCommand::PenUp,
+                CanonicalCommand::PlotAbsolute(Point { x: 0, y: 200 }),
+                CanonicalCommand::PenUp,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_overflow() {
+        let cmds = vec![
+            Command::PlotAbsolute(vec![Point { x: i32::MAX, y: 0 }]),
+            Command::PlotRelative(vec![Point { x: 1, y: 0 }]),
+        ];
+        assert_eq!(
+            canonicalize(cmds),
+            Err(InterpretError::CoordinateOverflow {
+                position: Point { x: i32::MAX, y: 0 },
+                offset: Point { x: 1, y: 0 },
+            })
+        );
+    }
 }
@@ -0,0 +1,256 @@
+use crate::device::Model;
+use crate::parse::display_path;
+use crate::{Command, Event, InterpretError, Interpreter, Point, Span};
+use std::fmt;
+use std::path::Path;
+
+/// How much a `Lint` matters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The plotter will draw something, but maybe not what was meant.
+    Warning,
+    /// The plotter will reject the command, or the plot won't work at all.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LintKind {
+    /// The command moves the pen to a point outside of the hard clip limits, which the plotter
+    /// will stop short of.
+    OutsideHardClipLimits(Point),
+    /// A `SP` for a pen that isn't in the carousel, along with how many pens it holds.
+    PenOutOfRange { pen: u8, pens: u8 },
+    /// A command that isn't in the model's instruction set.
+    Unsupported,
+    /// A command that doesn't fit in the model's buffer, along with both of their sizes, in
+    /// bytes.
+    TooLong { len: usize, buffer_size: usize },
+    /// The command draws a line, but no pen is selected.
+    NoPenSelected,
+    /// The command couldn't be interpreted at all.
+    Interpret(InterpretError),
+}
+
+/// A problem with a command, and where in the input the command was.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub span: Span,
+    pub mnemonic: &'static str,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            LintKind::OutsideHardClipLimits(_) | LintKind::NoPenSelected => Severity::Warning,
+            LintKind::PenOutOfRange { .. }
+            | LintKind::Unsupported
+            | LintKind::TooLong { .. }
+            | LintKind::Interpret(_) => Severity::Error,
+        }
+    }
+
+    /// Formats the lint in the style of a compiler diagnostic, like `ParseError::diagnostic`.
+    pub fn diagnostic(&self, path: &Path) -> String {
+        format!(
+            "{}: {}\n --> {}:{}:{}",
+            self.severity(),
+            self,
+            display_path(path),
+            self.span.line,
+            self.span.column
+        )
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LintKind::OutsideHardClipLimits(point) => write!(
+                f,
+                "`{}` goes to ({}, {}), which is outside of what the plotter can reach",
+                self.mnemonic, point.x, point.y
+            ),
+            LintKind::PenOutOfRange { pen, pens } => write!(
+                f,
+                "pen {} is out of range for `{}`, since the carousel only holds {} pens",
+                pen, self.mnemonic, pens
+            ),
+            LintKind::Unsupported => write!(f, "the plotter doesn't support `{}`", self.mnemonic),
+            LintKind::TooLong { len, buffer_size } => write!(
+                f,
+                "`{}` is {} bytes long, which doesn't fit in the plotter's {} byte buffer",
+                self.mnemonic, len, buffer_size
+            ),
+            LintKind::NoPenSelected => {
+                write!(
+                    f,
+                    "`{}` draws a line, but no pen is selected",
+                    self.mnemonic
+                )
+            }
+            LintKind::Interpret(e) => write!(f, "`{}`: {}", self.mnemonic, e),
+        }
+    }
+}
+
+/// Checks commands, one at a time, against what a plotter can do.
+///
+/// Commands the plotter doesn't support aren't executed, since the plotter ignores them, and
+/// drawing with no pen selected is only reported once until the next `SP`.
+pub struct Linter {
+    model: Model,
+    interpreter: Interpreter,
+    /// Whether drawing with no pen has been reported since the last `SP`.
+    reported_no_pen: bool,
+}
+
+impl Linter {
+    pub fn new(model: Model) -> Linter {
+        Linter {
+            model,
+            interpreter: Interpreter::new(),
+            reported_no_pen: false,
+        }
+    }
+
+    /// Checks a single command, which was at `span` in the input.
+    pub fn check(&mut self, cmd: Command, span: Span) -> Vec<Lint> {
+        let mnemonic = cmd.mnemonic();
+        let mut lints = vec![];
+        let mut lint = |kind| {
+            lints.push(Lint {
+                span,
+                mnemonic,
+                kind,
+            })
+        };
+        if span.len > self.model.buffer_size {
+            lint(LintKind::TooLong {
+                len: span.len,
+                buffer_size: self.model.buffer_size,
+            });
+        }
+        if !self.model.instruction_set.supports(&cmd) {
+            lint(LintKind::Unsupported);
+            return lints;
+        }
+        if let Command::SelectPen(pen) = cmd {
+            if pen > self.model.pens {
+                lint(LintKind::PenOutOfRange {
+                    pen,
+                    pens: self.model.pens,
+                });
+            }
+        }
+        let events = match self.interpreter.execute(cmd) {
+            Ok(events) => events,
+            Err(e) => {
+                lint(LintKind::Interpret(e));
+                return lints;
+            }
+        };
+        let mut outside = None;
+        for event in events {
+            match event {
+                Event::SelectPen(_) => self.reported_no_pen = false,
+                Event::Stroke { pen: 0, .. } if !self.reported_no_pen => {
+                    self.reported_no_pen = true;
+                    lint(LintKind::NoPenSelected);
+                }
+                Event::Stroke { to, .. } | Event::Travel { to, .. }
+                    if outside.is_none() && !self.model.contains(to) =>
+                {
+                    outside = Some(to);
+                }
+                _ => {}
+            }
+        }
+        if let Some(point) = outside {
+            lint(LintKind::OutsideHardClipLimits(point));
+        }
+        lints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::builtin_models;
+    use crate::read_commands;
+
+    fn lint(model: usize, hpgl: &str) -> Vec<(usize, &'static str, LintKind)> {
+        // 63 bytes long
+        let hpgl = hpgl.replace("LONG", &format!("PA 0{};", ",0".repeat(29)));
+        let mut linter = Linter::new(builtin_models()[model].clone());
+        read_commands(hpgl.as_bytes())
+            .spanned()
+            .flat_map(|cmd| {
+                let (cmd, span) = cmd.unwrap();
+                linter.check(cmd, span)
+            })
+            .map(|lint| (lint.span.line, lint.mnemonic, lint.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(
+            lint(
+                0,
+                "IN;PD 100,100;PU;\nSP8;SP9;PA 20000,0;PD 10300,0;PU;CI 100;\nLONG"
+            ),
+            vec![
+                (1, "PD", LintKind::NoPenSelected),
+                (2, "SP", LintKind::PenOutOfRange { pen: 9, pens: 8 }),
+                (
+                    2,
+                    "PA",
+                    LintKind::OutsideHardClipLimits(Point { x: 20000, y: 0 })
+                ),
+                (2, "CI", LintKind::Unsupported),
+                (
+                    3,
+                    "PA",
+                    LintKind::TooLong {
+                        len: 63,
+                        buffer_size: 60
+                    }
+                ),
+            ]
+        );
+        // the HP7475A has fewer pens, but a bigger buffer and circles
+        assert_eq!(
+            lint(1, "SP7;PA 500,500;CI 100;LONG"),
+            vec![(1, "SP", LintKind::PenOutOfRange { pen: 7, pens: 6 })]
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let lint = Lint {
+            span: Span {
+                offset: 4,
+                line: 2,
+                column: 1,
+                len: 4,
+            },
+            mnemonic: "SP",
+            kind: LintKind::PenOutOfRange { pen: 9, pens: 8 },
+        };
+        assert_eq!(lint.severity(), Severity::Error);
+        assert_eq!(
+            lint.diagnostic(Path::new("-")),
+            "error: pen 9 is out of range for `SP`, since the carousel only holds 8 pens\n --> <stdin>:2:1"
+        );
+    }
+}
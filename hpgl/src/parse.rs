@@ -0,0 +1,1008 @@
+use crate::command::{CharacterStroke, Command, InputMask, Scaling, ETX};
+use crate::Point;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseErrorKind {
+    UnknownMnemonic,
+    /// Commands that take points need an even number of coordinates - this holds how many were
+    /// actually given.
+    OddCoordinateCount(usize),
+    /// A parameter that should have been a number, holding the text that was there instead.
+    InvalidNumber(String),
+    /// A command was given a number of parameters it can't take.
+    WrongParameterCount(usize),
+    /// A `SP` pen number that doesn't fit in a `u8`.
+    PenOutOfRange(i64),
+    /// A parameter that's outside of the range the command accepts.
+    ValueOutOfRange(i64),
+    /// A `LB` that ran until the end of the input without reaching the label terminator.
+    UnterminatedLabel,
+    /// A `DT` terminator that isn't ascii, which the plotter can't match against a single byte.
+    NonAsciiTerminator(char),
+    /// The input couldn't be read, or wasn't valid utf-8.
+    Io(io::ErrorKind),
+}
+
+/// A command that couldn't be parsed, and where in the input it was.
+///
+/// `offset` is in bytes from the start of the input, while `line` and `column` are 1-indexed, and
+/// count characters. For bad parameters, the location is that of the parameter, otherwise it's
+/// that of the start of the command.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub mnemonic: String,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(location: Location, mnemonic: &str, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: location.offset,
+            line: location.line,
+            column: location.column,
+            mnemonic: mnemonic.to_string(),
+            kind,
+        }
+    }
+
+    /// Formats the error in the style of a compiler diagnostic, pointing at `path` (where `-` is
+    /// standard input).
+    pub fn diagnostic(&self, path: &Path) -> String {
+        format!(
+            "error: {}\n --> {}:{}:{}",
+            self,
+            display_path(path),
+            self.line,
+            self.column
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnknownMnemonic => write!(f, "unknown mnemonic `{}`", self.mnemonic),
+            ParseErrorKind::OddCoordinateCount(n) => write!(
+                f,
+                "`{}` takes pairs of coordinates, but was given {} value{}",
+                self.mnemonic,
+                n,
+                if *n == 1 { "" } else { "s" }
+            ),
+            ParseErrorKind::InvalidNumber(param) => write!(
+                f,
+                "expected a number as a parameter to `{}`, found `{}`",
+                self.mnemonic, param
+            ),
+            ParseErrorKind::WrongParameterCount(n) => write!(
+                f,
+                "`{}` can't take {} parameter{}",
+                self.mnemonic,
+                n,
+                if *n == 1 { "" } else { "s" }
+            ),
+            ParseErrorKind::ValueOutOfRange(value) => {
+                write!(f, "{} is out of range for `{}`", value, self.mnemonic)
+            }
+            ParseErrorKind::UnterminatedLabel => {
+                write!(f, "`{}` is missing its terminator", self.mnemonic)
+            }
+            ParseErrorKind::NonAsciiTerminator(c) => write!(
+                f,
+                "`{}` can't take `{}`, since label terminators have to be ascii",
+                self.mnemonic, c
+            ),
+            ParseErrorKind::Io(kind) => {
+                write!(f, "failed to read input: {}", io::Error::from(*kind))
+            }
+            ParseErrorKind::PenOutOfRange(pen) => {
+                write!(
+                    f,
+                    "pen number {} is out of range for `{}`",
+                    pen, self.mnemonic
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Where a command was in the input, as yielded by `CommandReader::spanned`.
+///
+/// `offset`, `line` and `column` are the start of the command, as in `ParseError`, and `len` is
+/// how many bytes it took up, including the `;` after it, if there was one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+/// Formats `path` for a diagnostic, where `-` is standard input.
+pub(crate) fn display_path(path: &Path) -> String {
+    if path == Path::new("-") {
+        "<stdin>".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Location {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn start() -> Location {
+        Location {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn advanced(mut self, text: &str) -> Location {
+        self.advance(text);
+        self
+    }
+}
+
+/// Reads commands one at a time from a `BufRead`, so that arbitrarily large inputs can be
+/// processed without holding all of them in memory.
+///
+/// Once reading from the underlying reader fails, the error is yielded and iteration stops.
+pub struct CommandReader<R> {
+    reader: R,
+    location: Location,
+    /// What ends a `LB`, as set by `DT`.
+    terminator: u8,
+    failed: bool,
+}
+
+impl<R: BufRead> CommandReader<R> {
+    pub fn new(reader: R) -> CommandReader<R> {
+        CommandReader {
+            reader,
+            location: Location::start(),
+            terminator: ETX as u8,
+            failed: false,
+        }
+    }
+
+    fn skip_separators(&mut self) -> io::Result<()> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            let skipped = buf
+                .iter()
+                .take_while(|b| **b == b';' || b.is_ascii_whitespace())
+                .count();
+            if skipped == 0 {
+                return Ok(());
+            }
+            // separators are always ascii, so this is valid utf-8
+            self.location
+                .advance(std::str::from_utf8(&buf[..skipped]).unwrap());
+            self.reader.consume(skipped);
+        }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    /// Reads the text of the next command, along with where it started. This doesn't include the
+    /// `;` at the end, but does include the terminator of a label.
+    fn read_command(&mut self) -> io::Result<Option<(Location, String)>> {
+        self.skip_separators()?;
+        let start = self.location;
+        let mut buf = vec![];
+        let mut separator = false;
+        while buf.len() < 2 {
+            match self.peek()? {
+                Some(b) if b != b';' => {
+                    buf.push(b);
+                    self.reader.consume(1);
+                }
+                _ => break,
+            }
+        }
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        match &buf[..] {
+            // everything up to the terminator is part of the label, even a `;`
+            b"LB" => {
+                self.reader.read_until(self.terminator, &mut buf)?;
+            }
+            // these take a single character, which can't be a `;`
+            b"DT" | b"SM" => {
+                if let Some(b) = self.peek()? {
+                    if b != b';' {
+                        // all of it, if it takes up more than one byte
+                        let len = match b {
+                            0xc0..=0xdf => 2,
+                            0xe0..=0xef => 3,
+                            0xf0..=0xf7 => 4,
+                            _ => 1,
+                        };
+                        for _ in 0..len {
+                            match self.peek()? {
+                                Some(b) => {
+                                    buf.push(b);
+                                    self.reader.consume(1);
+                                }
+                                None => break,
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                self.reader.read_until(b';', &mut buf)?;
+                if buf.last() == Some(&b';') {
+                    buf.pop();
+                    separator = true;
+                }
+            }
+        }
+        let text =
+            String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.location.advance(&text);
+        if separator {
+            self.location.advance(";");
+        }
+        Ok(Some((start, text)))
+    }
+}
+
+impl CommandReader<Box<dyn BufRead>> {
+    /// Opens the file at `path` for reading, treating `-` as standard input.
+    pub fn open(path: &Path) -> io::Result<CommandReader<Box<dyn BufRead>>> {
+        let reader: Box<dyn BufRead> = if path == Path::new("-") {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(path)?))
+        };
+        Ok(CommandReader::new(reader))
+    }
+}
+
+impl<R: BufRead> CommandReader<R> {
+    /// Yields where each command was in the input along with it.
+    pub fn spanned(self) -> Spanned<R> {
+        Spanned(self)
+    }
+
+    fn next_spanned(&mut self) -> Option<Result<(Command, Span), ParseError>> {
+        if self.failed {
+            return None;
+        }
+        match self.read_command() {
+            Ok(Some((location, text))) => {
+                let span = Span {
+                    offset: location.offset,
+                    line: location.line,
+                    column: location.column,
+                    len: self.location.offset - location.offset,
+                };
+                let trimmed = if text.starts_with("LB") {
+                    &text
+                } else {
+                    text.trim_end()
+                };
+                let cmd = parse_command(trimmed, location, self.terminator as char);
+                match cmd {
+                    Ok(Command::DefineTerminator(terminator)) => {
+                        self.terminator = terminator.unwrap_or(ETX) as u8;
+                    }
+                    Ok(Command::Initialize) | Ok(Command::Default) => self.terminator = ETX as u8,
+                    _ => {}
+                }
+                Some(cmd.map(|cmd| (cmd, span)))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                Some(Err(ParseError::new(
+                    self.location,
+                    "",
+                    ParseErrorKind::Io(e.kind()),
+                )))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CommandReader<R> {
+    type Item = Result<Command, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|cmd| cmd.map(|(cmd, _)| cmd))
+    }
+}
+
+/// A `CommandReader` that yields the `Span` of each command along with it.
+pub struct Spanned<R>(CommandReader<R>);
+
+impl<R: BufRead> Iterator for Spanned<R> {
+    type Item = Result<(Command, Span), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_spanned()
+    }
+}
+
+/// Reads commands from any `io::Read`, buffering it as needed.
+pub fn read_commands<R: Read>(reader: R) -> CommandReader<BufReader<R>> {
+    CommandReader::new(BufReader::new(reader))
+}
+
+pub fn parse_commands(cmd_str: String) -> Result<Vec<Command>, Vec<ParseError>> {
+    let mut cmds = vec![];
+    let mut errors = vec![];
+    for cmd in CommandReader::new(cmd_str.as_bytes()) {
+        match cmd {
+            Ok(cmd) => cmds.push(cmd),
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(cmds)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_command(cmd: &str, location: Location, terminator: char) -> Result<Command, ParseError> {
+    let mnemonic_len = cmd.char_indices().nth(2).map_or(cmd.len(), |(i, _)| i);
+    let (mnemonic, params) = cmd.split_at(mnemonic_len);
+    // these take characters rather than numbers as parameters, so they need to be handled before
+    // we try to split up the parameters
+    match mnemonic {
+        "LB" => {
+            return match params.strip_suffix(terminator) {
+                Some(text) => Ok(Command::Label(text.to_string())),
+                None => Err(ParseError::new(
+                    location,
+                    mnemonic,
+                    ParseErrorKind::UnterminatedLabel,
+                )),
+            }
+        }
+        "DT" => {
+            return match parse_char(mnemonic, params, location)? {
+                Some(c) if !c.is_ascii() => Err(ParseError::new(
+                    location.advanced(mnemonic),
+                    mnemonic,
+                    ParseErrorKind::NonAsciiTerminator(c),
+                )),
+                terminator => Ok(Command::DefineTerminator(terminator)),
+            }
+        }
+        "SM" => return Ok(Command::SymbolMode(parse_char(mnemonic, params, location)?)),
+        "SP" => return Ok(Command::SelectPen(parse_pen(mnemonic, params, location)?)),
+        _ => {}
+    }
+    let params = Params::new(mnemonic, params, location);
+    let cmd = match mnemonic {
+        "PU" => Command::PenUp(params.points()?),
+        "PD" => Command::PenDown(params.points()?),
+        "PA" => Command::PlotAbsolute(params.points()?),
+        "PR" => Command::PlotRelative(params.points()?),
+        "CI" => {
+            params.expect_count(&[1, 2])?;
+            Command::Circle(params.integer(0)?, params.optional_real_at(1)?)
+        }
+        "AA" | "AR" => {
+            params.expect_count(&[3, 4])?;
+            let (center, angle, chord_angle) = (
+                params.point(0)?,
+                params.real(2)?,
+                params.optional_real_at(3)?,
+            );
+            if mnemonic == "AA" {
+                Command::ArcAbsolute(center, angle, chord_angle)
+            } else {
+                Command::ArcRelative(center, angle, chord_angle)
+            }
+        }
+        "IP" => Command::InputP1P2(match params.expect_count(&[0, 2, 4])? {
+            0 => None,
+            2 => Some((params.point(0)?, None)),
+            _ => Some((params.point(0)?, Some(params.point(2)?))),
+        }),
+        "IW" => Command::InputWindow(match params.expect_count(&[0, 4])? {
+            0 => None,
+            _ => Some((params.point(0)?, params.point(2)?)),
+        }),
+        "SC" => Command::Scale(match params.expect_count(&[0, 4])? {
+            0 => None,
+            _ => Some(Scaling {
+                x_min: params.integer(0)?,
+                x_max: params.integer(1)?,
+                y_min: params.integer(2)?,
+                y_max: params.integer(3)?,
+            }),
+        }),
+        "VS" => Command::VelocitySelect(params.optional_real()?),
+        "LT" => Command::LineType(match params.expect_count(&[0, 1, 2])? {
+            0 => None,
+            1 => Some((params.small(0)?, None)),
+            _ => Some((params.small(0)?, Some(params.real(1)?))),
+        }),
+        "TL" => Command::TickLength(match params.expect_count(&[0, 1, 2])? {
+            0 => None,
+            1 => Some((params.real(0)?, None)),
+            _ => Some((params.real(0)?, Some(params.real(1)?))),
+        }),
+        "CA" => Command::DesignateAlternateSet(params.optional_small()?),
+        "CS" => Command::DesignateStandardSet(params.optional_small()?),
+        "CP" => Command::CharacterPlot(params.optional_pair()?),
+        "DI" => Command::AbsoluteDirection(params.optional_pair()?),
+        "DR" => Command::RelativeDirection(params.optional_pair()?),
+        "SI" => Command::AbsoluteCharacterSize(params.optional_pair()?),
+        "SR" => Command::RelativeCharacterSize(params.optional_pair()?),
+        "SL" => Command::CharacterSlant(params.optional_real()?),
+        "UC" => Command::UserDefinedCharacter(params.character_strokes()?),
+        "IM" => Command::InputMask(match params.expect_count(&[0, 1, 2, 3])? {
+            0 => None,
+            count => Some(InputMask {
+                error: params.small(0)?,
+                service_request: if count > 1 {
+                    Some(params.small(1)?)
+                } else {
+                    None
+                },
+                parallel_poll: if count > 2 {
+                    Some(params.small(2)?)
+                } else {
+                    None
+                },
+            }),
+        }),
+        _ => {
+            let cmd = match mnemonic {
+                "IN" => Command::Initialize,
+                "DF" => Command::Default,
+                "XT" => Command::XTick,
+                "YT" => Command::YTick,
+                "SA" => Command::SelectAlternateSet,
+                "SS" => Command::SelectStandardSet,
+                "DC" => Command::DigitizeClear,
+                "DP" => Command::DigitizePoint,
+                "OA" => Command::OutputActualPosition,
+                "OC" => Command::OutputCommandedPosition,
+                "OD" => Command::OutputDigitizedPoint,
+                "OE" => Command::OutputError,
+                "OF" => Command::OutputFactors,
+                "OH" => Command::OutputHardClipLimits,
+                "OI" => Command::OutputIdentification,
+                "OO" => Command::OutputOptions,
+                "OP" => Command::OutputP1P2,
+                "OS" => Command::OutputStatus,
+                "OW" => Command::OutputWindow,
+                _ => {
+                    return Err(ParseError::new(
+                        location,
+                        mnemonic,
+                        ParseErrorKind::UnknownMnemonic,
+                    ))
+                }
+            };
+            params.expect_count(&[0])?;
+            cmd
+        }
+    };
+    Ok(cmd)
+}
+
+/// The comma separated parameters of a single command, along with where each of them starts.
+struct Params<'a> {
+    mnemonic: &'a str,
+    location: Location,
+    values: Vec<(Location, &'a str)>,
+}
+
+impl<'a> Params<'a> {
+    fn new(mnemonic: &'a str, params: &'a str, location: Location) -> Params<'a> {
+        let mut values = vec![];
+        if !params.is_empty() {
+            let mut param_location = location.advanced(mnemonic);
+            for param in params.split(',') {
+                let leading_whitespace = &param[..param.len() - param.trim_start().len()];
+                values.push((param_location.advanced(leading_whitespace), param.trim()));
+                param_location.advance(param);
+                param_location.advance(",");
+            }
+        }
+        Params {
+            mnemonic,
+            location,
+            values,
+        }
+    }
+
+    fn error(&self, location: Location, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(location, self.mnemonic, kind)
+    }
+
+    /// Checks that the number of parameters is one of `counts`, and returns it.
+    fn expect_count(&self, counts: &[usize]) -> Result<usize, ParseError> {
+        let count = self.values.len();
+        if counts.contains(&count) {
+            Ok(count)
+        } else {
+            Err(self.error(self.location, ParseErrorKind::WrongParameterCount(count)))
+        }
+    }
+
+    fn integer<T: FromStr>(&self, i: usize) -> Result<T, ParseError> {
+        let (location, param) = self.values[i];
+        param
+            .parse()
+            .map_err(|_| self.error(location, ParseErrorKind::InvalidNumber(param.to_string())))
+    }
+
+    /// A parameter that's an integer in the range of a `u8`, like a character set number.
+    fn small(&self, i: usize) -> Result<u8, ParseError> {
+        let value: i64 = self.integer(i)?;
+        u8::try_from(value)
+            .map_err(|_| self.error(self.values[i].0, ParseErrorKind::ValueOutOfRange(value)))
+    }
+
+    /// A parameter that may have a fractional part. Unlike `f64::from_str`, this doesn't accept
+    /// exponents, infinities or NaN, since the plotter doesn't.
+    fn real(&self, i: usize) -> Result<f64, ParseError> {
+        let (location, param) = self.values[i];
+        let unsigned = param
+            .strip_prefix('-')
+            .or_else(|| param.strip_prefix('+'))
+            .unwrap_or(param);
+        let mut parts = unsigned.splitn(2, '.');
+        let whole = parts.next().unwrap_or("");
+        let fraction = parts.next().unwrap_or("");
+        let is_decimal = !(whole.is_empty() && fraction.is_empty())
+            && whole.chars().all(|c| c.is_ascii_digit())
+            && fraction.chars().all(|c| c.is_ascii_digit());
+        match param.parse() {
+            Ok(value) if is_decimal => Ok(value),
+            _ => Err(self.error(location, ParseErrorKind::InvalidNumber(param.to_string()))),
+        }
+    }
+
+    fn point(&self, i: usize) -> Result<Point, ParseError> {
+        Ok(Point {
+            x: self.integer(i)?,
+            y: self.integer(i + 1)?,
+        })
+    }
+
+    fn points(&self) -> Result<Vec<Point>, ParseError> {
+        if !self.values.len().is_multiple_of(2) {
+            return Err(self.error(
+                self.location,
+                ParseErrorKind::OddCoordinateCount(self.values.len()),
+            ));
+        }
+        (0..self.values.len())
+            .step_by(2)
+            .map(|i| self.point(i))
+            .collect()
+    }
+
+    fn optional_small(&self) -> Result<Option<u8>, ParseError> {
+        match self.expect_count(&[0, 1])? {
+            0 => Ok(None),
+            _ => Ok(Some(self.small(0)?)),
+        }
+    }
+
+    fn optional_real(&self) -> Result<Option<f64>, ParseError> {
+        match self.expect_count(&[0, 1])? {
+            0 => Ok(None),
+            _ => Ok(Some(self.real(0)?)),
+        }
+    }
+
+    /// A trailing parameter that may have been left off.
+    fn optional_real_at(&self, i: usize) -> Result<Option<f64>, ParseError> {
+        if i < self.values.len() {
+            Ok(Some(self.real(i)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn optional_pair(&self) -> Result<Option<(f64, f64)>, ParseError> {
+        match self.expect_count(&[0, 2])? {
+            0 => Ok(None),
+            _ => Ok(Some((self.real(0)?, self.real(1)?))),
+        }
+    }
+
+    /// `UC` parameters, where `99` and `-99` put the pen down and up, and anything else is a pair
+    /// of grid units to move by.
+    fn character_strokes(&self) -> Result<Vec<CharacterStroke>, ParseError> {
+        let mut strokes = vec![];
+        let mut i = 0;
+        while i < self.values.len() {
+            match self.integer(i)? {
+                99 => strokes.push(CharacterStroke::PenDown),
+                -99 => strokes.push(CharacterStroke::PenUp),
+                x => {
+                    i += 1;
+                    if i == self.values.len() {
+                        return Err(self.error(
+                            self.location,
+                            ParseErrorKind::WrongParameterCount(self.values.len()),
+                        ));
+                    }
+                    strokes.push(CharacterStroke::Move(x, self.integer(i)?));
+                }
+            }
+            i += 1;
+        }
+        Ok(strokes)
+    }
+}
+
+/// Parses the single character taken by `DT` and `SM`, if there is one.
+fn parse_char(
+    mnemonic: &str,
+    params: &str,
+    location: Location,
+) -> Result<Option<char>, ParseError> {
+    let mut chars = params.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(c), None) => Ok(Some(c)),
+        (Some(_), Some(_)) => Err(ParseError::new(
+            location,
+            mnemonic,
+            ParseErrorKind::WrongParameterCount(params.chars().count()),
+        )),
+    }
+}
+
+fn parse_pen(mnemonic: &str, params: &str, location: Location) -> Result<u8, ParseError> {
+    // `SP;` puts the pen away, the same as `SP0;`
+    if params.trim().is_empty() {
+        return Ok(0);
+    }
+    let params = Params::new(mnemonic, params, location);
+    params.expect_count(&[1])?;
+    let pen: i64 = params.integer(0)?;
+    u8::try_from(pen)
+        .map_err(|_| params.error(params.values[0].0, ParseErrorKind::PenOutOfRange(pen)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_parse_command(cmd: &str, expected: Result<Command, ParseErrorKind>) {
+        let parsed = parse_command(cmd, Location::start(), ETX).map_err(|e| e.kind);
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_parse_multiple_commands() {
+        assert_eq!(
+            parse_commands("  PU  ; PD  ; ".to_string()),
+            Ok(vec![Command::PenUp(vec![]), Command::PenDown(vec![])])
+        );
+        assert_eq!(parse_commands("".to_string()), Ok(vec![]));
+        assert_eq!(parse_commands("\n".to_string()), Ok(vec![]));
+        assert_eq!(parse_commands("  ".to_string()), Ok(vec![]));
+        assert_eq!(
+            parse_commands("  PU  ; command_not_valid ; PD  ; ".to_string()),
+            Err(vec![ParseError {
+                offset: 8,
+                line: 1,
+                column: 9,
+                mnemonic: "co".to_string(),
+                kind: ParseErrorKind::UnknownMnemonic,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parser() {
+        check_parse_command("", Err(ParseErrorKind::UnknownMnemonic));
+        check_parse_command("a", Err(ParseErrorKind::UnknownMnemonic));
+        check_parse_command("command_not_valid", Err(ParseErrorKind::UnknownMnemonic));
+        check_parse_command("PU ", Err(ParseErrorKind::OddCoordinateCount(1)));
+        check_parse_command(" PU", Err(ParseErrorKind::UnknownMnemonic));
+        check_parse_command(
+            "PA foo, bar",
+            Err(ParseErrorKind::InvalidNumber("foo".to_string())),
+        );
+        check_parse_command("PA 1,2,3", Err(ParseErrorKind::OddCoordinateCount(3)));
+        check_parse_command("SP 256", Err(ParseErrorKind::PenOutOfRange(256)));
+        check_parse_command("SP -1", Err(ParseErrorKind::PenOutOfRange(-1)));
+        check_parse_command(
+            "SP one",
+            Err(ParseErrorKind::InvalidNumber("one".to_string())),
+        );
+        check_parse_command("PU", Ok(Command::PenUp(vec![])));
+        check_parse_command("SP", Ok(Command::SelectPen(0)));
+        check_parse_command("SP 3", Ok(Command::SelectPen(3)));
+        check_parse_command(
+            "PD 10, -20,30,40",
+            Ok(Command::PenDown(vec![
+                Point { x: 10, y: -20 },
+                Point { x: 30, y: 40 },
+            ])),
+        );
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let errors =
+            parse_commands("IN;\nSP1;\r\nPA 100, 1O0;\n  XY;\nPD 1,2,3;".to_string()).unwrap_err();
+        let locations: Vec<_> = errors
+            .iter()
+            .map(|e| (e.offset, e.line, e.column, e.mnemonic.as_str()))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![(18, 3, 9, "PA"), (25, 4, 3, "XY"), (29, 5, 1, "PD")]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "expected a number as a parameter to `PA`, found `1O0`"
+        );
+        assert_eq!(
+            errors[0].diagnostic(Path::new("plot.hpgl")),
+            "error: expected a number as a parameter to `PA`, found `1O0`\n --> plot.hpgl:3:9"
+        );
+    }
+
+    #[test]
+    fn test_command_reader() {
+        let input = "IN;SP1;\nPA 100,200;\n  PD 300,400, 500,600;\nXY;PU";
+        // a tiny buffer makes sure commands that span multiple reads are put back together
+        let reader = BufReader::with_capacity(3, input.as_bytes());
+        let cmds: Vec<_> = CommandReader::new(reader)
+            .map(|c| c.map_err(|e| e.kind))
+            .collect();
+        assert_eq!(
+            cmds,
+            vec![
+                Ok(Command::Initialize),
+                Ok(Command::SelectPen(1)),
+                Ok(Command::PlotAbsolute(vec![Point { x: 100, y: 200 }])),
+                Ok(Command::PenDown(vec![
+                    Point { x: 300, y: 400 },
+                    Point { x: 500, y: 600 }
+                ])),
+                Err(ParseErrorKind::UnknownMnemonic),
+                Ok(Command::PenUp(vec![])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "IN;\n  PA 100,200;LBhi\x03PU";
+        let spans: Vec<_> = read_commands(input.as_bytes())
+            .spanned()
+            .map(|c| {
+                let span = c.unwrap().1;
+                (span.offset, span.line, span.column, span.len)
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(0, 1, 1, 3), (6, 2, 3, 11), (17, 2, 14, 5), (22, 2, 19, 2)]
+        );
+    }
+
+    struct FailingReader<'a>(&'a [u8]);
+
+    impl<'a> Read for FailingReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "gone"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_parameters() {
+        // a tiny buffer makes sure characters split across reads are put back together
+        let input = "SMé;PA 1,1;SM€PA 2,2;DTé;PU;";
+        let reader = BufReader::with_capacity(1, input.as_bytes());
+        let cmds: Vec<_> = CommandReader::new(reader)
+            .map(|c| c.map_err(|e| (e.column, e.kind)))
+            .collect();
+        assert_eq!(
+            cmds,
+            vec![
+                Ok(Command::SymbolMode(Some('é'))),
+                Ok(Command::PlotAbsolute(vec![Point { x: 1, y: 1 }])),
+                Ok(Command::SymbolMode(Some('€'))),
+                Ok(Command::PlotAbsolute(vec![Point { x: 2, y: 2 }])),
+                Err((24, ParseErrorKind::NonAsciiTerminator('é'))),
+                Ok(Command::PenUp(vec![])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_reader_io_error() {
+        let cmds: Vec<_> = read_commands(FailingReader(b"PU;PD;"))
+            .map(|c| c.map_err(|e| (e.offset, e.kind)))
+            .collect();
+        assert_eq!(
+            cmds,
+            vec![
+                Ok(Command::PenUp(vec![])),
+                Ok(Command::PenDown(vec![])),
+                Err((6, ParseErrorKind::Io(io::ErrorKind::BrokenPipe))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_all_instructions() {
+        let cmds = parse_commands(include_str!("../testdata/all_instructions.hpgl").to_string());
+        let p = |x, y| Point { x, y };
+        assert_eq!(
+            cmds,
+            Ok(vec![
+                Command::Initialize,
+                Command::Default,
+                Command::InputP1P2(Some((p(250, 596), Some(p(10250, 7796))))),
+                Command::InputP1P2(Some((p(0, 0), None))),
+                Command::InputP1P2(None),
+                Command::InputWindow(Some((p(0, 0), p(5000, 5000)))),
+                Command::InputWindow(None),
+                Command::Scale(Some(Scaling {
+                    x_min: 0,
+                    x_max: 100,
+                    y_min: 0,
+                    y_max: 100
+                })),
+                Command::Scale(None),
+                Command::Circle(500, None),
+                Command::Circle(-250, Some(10.0)),
+                Command::ArcAbsolute(p(100, 100), 90.0, None),
+                Command::ArcRelative(p(-50, 0), -180.5, Some(2.5)),
+                Command::VelocitySelect(Some(2.0)),
+                Command::VelocitySelect(None),
+                Command::LineType(Some((2, Some(4.5)))),
+                Command::LineType(Some((6, None))),
+                Command::LineType(None),
+                Command::TickLength(Some((1.5, Some(0.0)))),
+                Command::TickLength(Some((2.0, None))),
+                Command::TickLength(None),
+                Command::XTick,
+                Command::YTick,
+                Command::Label("Hello; world!".to_string()),
+                Command::DefineTerminator(Some('*')),
+                Command::Label("star".to_string()),
+                Command::SymbolMode(Some('.')),
+                Command::SymbolMode(None),
+                Command::DefineTerminator(None),
+                Command::Label("back to etx".to_string()),
+                Command::DesignateAlternateSet(Some(1)),
+                Command::DesignateAlternateSet(None),
+                Command::DesignateStandardSet(Some(0)),
+                Command::DesignateStandardSet(None),
+                Command::SelectAlternateSet,
+                Command::SelectStandardSet,
+                Command::CharacterPlot(Some((1.5, -1.0))),
+                Command::CharacterPlot(None),
+                Command::AbsoluteDirection(Some((1.0, 0.0))),
+                Command::AbsoluteDirection(None),
+                Command::RelativeDirection(Some((0.0, 1.0))),
+                Command::RelativeDirection(None),
+                Command::AbsoluteCharacterSize(Some((0.19, 0.27))),
+                Command::AbsoluteCharacterSize(None),
+                Command::RelativeCharacterSize(Some((1.5, 2.0))),
+                Command::RelativeCharacterSize(None),
+                Command::CharacterSlant(Some(0.5)),
+                Command::CharacterSlant(None),
+                Command::UserDefinedCharacter(vec![
+                    CharacterStroke::Move(4, 0),
+                    CharacterStroke::PenDown,
+                    CharacterStroke::Move(0, 8),
+                    CharacterStroke::PenUp,
+                    CharacterStroke::Move(-4, -8),
+                ]),
+                Command::InputMask(Some(InputMask {
+                    error: 233,
+                    service_request: Some(0),
+                    parallel_poll: Some(0),
+                })),
+                Command::InputMask(Some(InputMask {
+                    error: 223,
+                    service_request: None,
+                    parallel_poll: None,
+                })),
+                Command::InputMask(None),
+                Command::DigitizeClear,
+                Command::DigitizePoint,
+                Command::OutputActualPosition,
+                Command::OutputCommandedPosition,
+                Command::OutputDigitizedPoint,
+                Command::OutputError,
+                Command::OutputFactors,
+                Command::OutputHardClipLimits,
+                Command::OutputIdentification,
+                Command::OutputOptions,
+                Command::OutputP1P2,
+                Command::OutputStatus,
+                Command::OutputWindow,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parameter_errors() {
+        check_parse_command("IW 0,0,10", Err(ParseErrorKind::WrongParameterCount(3)));
+        check_parse_command("IN 1", Err(ParseErrorKind::WrongParameterCount(1)));
+        check_parse_command("CI", Err(ParseErrorKind::WrongParameterCount(0)));
+        check_parse_command("AA 0,0", Err(ParseErrorKind::WrongParameterCount(2)));
+        check_parse_command("LT 300", Err(ParseErrorKind::ValueOutOfRange(300)));
+        check_parse_command(
+            "VS inf",
+            Err(ParseErrorKind::InvalidNumber("inf".to_string())),
+        );
+        check_parse_command(
+            "SL 1e3",
+            Err(ParseErrorKind::InvalidNumber("1e3".to_string())),
+        );
+        check_parse_command(
+            "SI .,1",
+            Err(ParseErrorKind::InvalidNumber(".".to_string())),
+        );
+        check_parse_command("UC 1,2,99,3", Err(ParseErrorKind::WrongParameterCount(4)));
+        check_parse_command("LBno terminator", Err(ParseErrorKind::UnterminatedLabel));
+        check_parse_command(
+            "SI -.5,+2.",
+            Ok(Command::AbsoluteCharacterSize(Some((-0.5, 2.0)))),
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let cmds = parse_commands(
+            "PU; PA 10,20, 30,40; SP 2; IP 0,0; SC 0,10,-10,0; LT 2, 1.25; LBa;b\x03 UC 1,1,99,-2,0; IM 1,2;"
+                .to_string(),
+        )
+        .unwrap();
+        let out: String = cmds.iter().map(|cmd| cmd.to_string()).collect();
+        assert_eq!(
+            out,
+            "PU;PA10,20,30,40;SP2;IP0,0;SC0,10,-10,0;LT2,1.25;LBa;b\x03UC1,1,99,-2,0;IM1,2;"
+        );
+    }
+}
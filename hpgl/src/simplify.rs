@@ -0,0 +1,122 @@
+use crate::{Point, Polyline};
+
+/// How far `p` is from the closest point on the line segment from `a` to `b`.
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let (px, py) = (f64::from(p.x), f64::from(p.y));
+    let (ax, ay) = (f64::from(a.x), f64::from(a.y));
+    let (bx, by) = (f64::from(b.x), f64::from(b.y));
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((px - ax) * dx + (py - ay) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+    (px - (ax + t * dx)).hypot(py - (ay + t * dy))
+}
+
+/// Drops points from a polyline that don't change its shape by more than `tolerance` plotter
+/// units, using the Ramer-Douglas-Peucker algorithm.
+///
+/// Every point of the original is within `tolerance` of the simplified polyline, and every point
+/// of the simplified polyline is within `tolerance` of the original. The first and last points
+/// are always kept, so closed shapes stay closed.
+pub fn simplify(polyline: &[Point], tolerance: f64) -> Polyline {
+    if polyline.len() < 3 {
+        return polyline.to_vec();
+    }
+    let mut keep = vec![false; polyline.len()];
+    keep[0] = true;
+    keep[polyline.len() - 1] = true;
+    // the runs of points that still need to be looked at, kept on a stack rather than recursing,
+    // since some polylines (like coastlines) have an awful lot of points
+    let mut runs = vec![(0, polyline.len() - 1)];
+    while let Some((first, last)) = runs.pop() {
+        let (a, b) = (polyline[first], polyline[last]);
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance_to_segment(polyline[i], a, b)))
+            .fold(None, |best: Option<(usize, f64)>, (i, d)| match best {
+                Some((_, best_d)) if best_d >= d => best,
+                _ => Some((i, d)),
+            });
+        if let Some((i, d)) = farthest {
+            if d > tolerance {
+                keep[i] = true;
+                runs.push((first, i));
+                runs.push((i, last));
+            }
+        }
+    }
+    polyline
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    /// How far `p` is from the closest point on a polyline.
+    fn distance_to_polyline(p: Point, polyline: &[Point]) -> f64 {
+        if polyline.len() == 1 {
+            return distance_to_segment(p, polyline[0], polyline[0]);
+        }
+        polyline
+            .windows(2)
+            .map(|s| distance_to_segment(p, s[0], s[1]))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    #[test]
+    fn test_simplify() {
+        let line = vec![p(0, 0), p(100, 3), p(200, 1), p(300, 0), p(300, 300)];
+        assert_eq!(simplify(&line, 0.0), line);
+        assert_eq!(simplify(&line, 5.0), vec![p(0, 0), p(300, 0), p(300, 300)]);
+        assert_eq!(
+            simplify(&line, 2.5),
+            vec![p(0, 0), p(100, 3), p(300, 0), p(300, 300)]
+        );
+        // closed shapes stay closed
+        let square = vec![p(0, 0), p(100, 0), p(100, 100), p(0, 100), p(0, 0)];
+        assert_eq!(simplify(&square, 10.0), square);
+        assert_eq!(simplify(&[p(1, 1)], 10.0), vec![p(1, 1)]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_within_tolerance(
+            polyline in prop::collection::vec((-1000..1000, -1000..1000), 1..50),
+            tolerance in 0.0..200.0,
+        ) {
+            let polyline: Vec<Point> = polyline.into_iter().map(|(x, y)| p(x, y)).collect();
+            let simplified = simplify(&polyline, tolerance);
+            prop_assert_eq!(simplified.first(), polyline.first());
+            prop_assert_eq!(simplified.last(), polyline.last());
+            for point in &polyline {
+                prop_assert!(distance_to_polyline(*point, &simplified) <= tolerance + 1e-9);
+            }
+            // and points all along the lines of the simplified polyline are close to the original
+            for s in simplified.windows(2) {
+                for i in 0..=10 {
+                    let t = f64::from(i) / 10.0;
+                    let along = p(
+                        (f64::from(s[0].x) + t * f64::from(s[1].x - s[0].x)).round() as i32,
+                        (f64::from(s[0].y) + t * f64::from(s[1].y - s[0].y)).round() as i32,
+                    );
+                    // rounding to plotter units moves the point by up to about 0.71 units
+                    prop_assert!(
+                        distance_to_polyline(along, &polyline) <= tolerance + 0.75
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::device::Model;
+use crate::{Command, Event, InterpretError, Interpreter, Point, UNITS_PER_CM};
+use std::collections::BTreeMap;
+
+/// How much drawing a plot does, and roughly how long it takes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// How far each pen moves while it's down, in plotter units. Lines drawn with no pen
+    /// selected are under pen `0`.
+    pub pen_down_distance: BTreeMap<u8, f64>,
+    /// How far the pen moves while it's up, in plotter units.
+    pub travel_distance: f64,
+    /// How many times the pen is lifted from the paper.
+    pub pen_lifts: usize,
+    /// How many times a different pen is selected, including picking up the first one. Putting
+    /// the pen away with `SP0` doesn't count.
+    pub pen_changes: usize,
+    /// The lower left and upper right corners of everything that's drawn, or `None` if nothing
+    /// is.
+    pub bounds: Option<(Point, Point)>,
+    /// The estimated time the plot takes, in seconds.
+    pub time: f64,
+}
+
+impl Stats {
+    /// The total distance drawn with all of the pens, in plotter units.
+    pub fn total_pen_down_distance(&self) -> f64 {
+        self.pen_down_distance.values().sum()
+    }
+}
+
+/// Collects `Stats` for commands, one at a time, as they'd be plotted on a given model.
+///
+/// The time estimate only counts the time the pen spends moving at full speed (the speed set by
+/// `VS` while it's down), lifting and lowering the pen, and changing pens. Plotters also slow
+/// down at corners, and spend time talking over the serial port, so the real plot will take
+/// somewhat longer.
+pub struct StatsCollector {
+    model: Model,
+    interpreter: Interpreter,
+    stats: Stats,
+    pen_down: bool,
+    pen: u8,
+}
+
+impl StatsCollector {
+    pub fn new(model: Model) -> StatsCollector {
+        // dashes and labels are drawn by lifting and lowering the pen, which takes time
+        let mut interpreter = Interpreter::new();
+        interpreter.set_expand_line_types(true);
+        interpreter.set_expand_labels(true);
+        interpreter.set_hard_clip_limits(Some(model.hard_clip_limits));
+        StatsCollector {
+            model,
+            interpreter,
+            stats: Stats::default(),
+            pen_down: false,
+            pen: 0,
+        }
+    }
+
+    pub fn execute(&mut self, cmd: Command) -> Result<(), InterpretError> {
+        let events = self.interpreter.execute(cmd)?;
+        // the plotter can't go any faster than its maximum, and treats `VS 0` as the maximum
+        let velocity = match self.interpreter.state().velocity {
+            Some(v) if v > 0.0 => v.min(self.model.max_velocity),
+            _ => self.model.max_velocity,
+        };
+        for event in events {
+            match event {
+                Event::Stroke { pen, from, to } => {
+                    let distance = distance(from, to);
+                    *self.stats.pen_down_distance.entry(pen).or_insert(0.0) += distance;
+                    self.stats.time += distance / UNITS_PER_CM / velocity;
+                    if pen != 0 {
+                        self.extend_bounds(from);
+                        self.extend_bounds(to);
+                    }
+                }
+                Event::Travel { from, to } => {
+                    let distance = distance(from, to);
+                    self.stats.travel_distance += distance;
+                    self.stats.time += distance / UNITS_PER_CM / self.model.travel_velocity;
+                }
+                Event::PenUp if self.pen_down => {
+                    self.pen_down = false;
+                    self.stats.pen_lifts += 1;
+                    self.stats.time += self.model.pen_dwell;
+                }
+                Event::PenDown if !self.pen_down => {
+                    self.pen_down = true;
+                    self.stats.time += self.model.pen_dwell;
+                }
+                Event::SelectPen(pen) if pen != self.pen => {
+                    self.pen = pen;
+                    // putting the pen away is quick next to picking up another one, and every
+                    // plot ends with it
+                    if pen != 0 {
+                        self.stats.pen_changes += 1;
+                        self.stats.time += self.model.pen_change_time;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    fn extend_bounds(&mut self, point: Point) {
+        self.stats.bounds = Some(match self.stats.bounds {
+            None => (point, point),
+            Some((min, max)) => (
+                Point {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                },
+                Point {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                },
+            ),
+        });
+    }
+}
+
+fn distance(from: Point, to: Point) -> f64 {
+    let dx = f64::from(to.x) - f64::from(from.x);
+    let dy = f64::from(to.y) - f64::from(from.y);
+    dx.hypot(dy)
+}
+
+/// Collects `Stats` for a list of commands.
+pub fn stats<I: IntoIterator<Item = Command>>(
+    cmds: I,
+    model: &Model,
+) -> Result<Stats, InterpretError> {
+    let mut collector = StatsCollector::new(model.clone());
+    for cmd in cmds {
+        collector.execute(cmd)?;
+    }
+    Ok(collector.stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::builtin_models;
+    use crate::parse_commands;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut model = builtin_models()[0].clone();
+        model.max_velocity = 10.0;
+        model.travel_velocity = 20.0;
+        model.pen_dwell = 0.5;
+        model.pen_change_time = 2.0;
+        let cmds = parse_commands(
+            "SP1;PA 0,4000;PD 3000,4000;PU 3000,0;SP2;VS 5;PD 3000,4000;PU;SP2;PD 100000,4000;PU;SP0;"
+                .to_string(),
+        )
+        .unwrap();
+        let stats = stats(cmds, &model).unwrap();
+
+        let mut pen_down_distance = BTreeMap::new();
+        // the last line is clipped to the hard clip limits
+        pen_down_distance.insert(1, 3000.0);
+        pen_down_distance.insert(2, 4000.0 + 7300.0);
+        assert_eq!(stats.pen_down_distance, pen_down_distance);
+        assert_eq!(stats.total_pen_down_distance(), 14300.0);
+        assert_eq!(stats.travel_distance, 4000.0 + 4000.0 + 100000.0 - 10300.0);
+        assert_eq!(stats.pen_lifts, 3);
+        // putting the pen away at the end isn't a pen change
+        assert_eq!(stats.pen_changes, 2);
+        assert_eq!(stats.bounds, Some((p(0, 0), p(10300, 4000))));
+
+        let drawing = 3000.0 / 400.0 / 10.0 + 4000.0 / 400.0 / 5.0 + 7300.0 / 400.0 / 5.0;
+        let travel = stats.travel_distance / 400.0 / 20.0;
+        let expected = drawing + travel + 6.0 * 0.5 + 2.0 * 2.0;
+        assert!((stats.time - expected).abs() < 1e-9, "{}", stats.time);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::device::Model;
+use crate::{CanonicalCommand, Command, Point};
+use std::fmt;
+
+/// An affine transform, as the top two rows of a 3x3 matrix, so that a point `(x, y)` goes to
+/// `(a x + b y + c, d x + e y + f)` for a matrix of `[[a, b, c], [d, e, f]]`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub matrix: [[f64; 3]; 2],
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        }
+    }
+
+    pub fn translate(dx: f64, dy: f64) -> Transform {
+        Transform {
+            matrix: [[1.0, 0.0, dx], [0.0, 1.0, dy]],
+        }
+    }
+
+    /// Scales about the origin.
+    pub fn scale(sx: f64, sy: f64) -> Transform {
+        Transform {
+            matrix: [[sx, 0.0, 0.0], [0.0, sy, 0.0]],
+        }
+    }
+
+    /// Rotates counterclockwise about the origin, by an angle in degrees.
+    pub fn rotate(degrees: f64) -> Transform {
+        // whole numbers of quarter turns are common, and should come out exact
+        let quarter_turns = [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)];
+        let degrees = degrees.rem_euclid(360.0);
+        let (sin, cos) = if degrees % 90.0 == 0.0 {
+            // rem_euclid can round up to 360 for tiny negative angles
+            quarter_turns[(degrees / 90.0) as usize % 4]
+        } else {
+            degrees.to_radians().sin_cos()
+        };
+        Transform {
+            matrix: [[cos, -sin, 0.0], [sin, cos, 0.0]],
+        }
+    }
+
+    /// Mirrors across the x axis, so that `y` goes to `-y`.
+    pub fn mirror_x() -> Transform {
+        Transform::scale(1.0, -1.0)
+    }
+
+    /// Mirrors across the y axis, so that `x` goes to `-x`.
+    pub fn mirror_y() -> Transform {
+        Transform::scale(-1.0, 1.0)
+    }
+
+    /// The transform that applies this one, and then `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        let [[a, b, c], [d, e, f]] = self.matrix;
+        let [[na, nb, nc], [nd, ne, nf]] = next.matrix;
+        Transform {
+            matrix: [
+                [na * a + nb * d, na * b + nb * e, na * c + nb * f + nc],
+                [nd * a + ne * d, nd * b + ne * e, nd * c + ne * f + nf],
+            ],
+        }
+    }
+
+    /// Transforms a point, without rounding it.
+    pub fn apply_exact(&self, x: f64, y: f64) -> (f64, f64) {
+        let [[a, b, c], [d, e, f]] = self.matrix;
+        (a * x + b * y + c, d * x + e * y + f)
+    }
+
+    /// Transforms a point, rounding it to the nearest plotter unit (with halves rounded away from
+    /// zero). Returns `None` if it ends up outside of the representable coordinate range.
+    pub fn apply(&self, point: Point) -> Option<Point> {
+        let (x, y) = self.apply_exact(f64::from(point.x), f64::from(point.y));
+        let (x, y) = (x.round(), y.round());
+        let range = f64::from(i32::MIN)..=f64::from(i32::MAX);
+        if range.contains(&x) && range.contains(&y) {
+            Some(Point {
+                x: x as i32,
+                y: y as i32,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TransformError {
+    /// A point ended up outside of the representable coordinate range.
+    OutOfRange { point: Point },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::OutOfRange { point } => write!(
+                f,
+                "the point ({}, {}) is out of range once it's transformed",
+                point.x, point.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Applies a transform to canonical commands (see `canonicalize`).
+///
+/// Every point is transformed from where it was in the original, and rounded once, so rounding
+/// doesn't build up over a drawing. Passed through commands are left alone, so unexpanded
+/// labels (see `CanonicalizeOptions::expand_labels`) start at the transformed point, but aren't
+/// scaled or rotated themselves.
+pub fn transform(
+    cmds: Vec<CanonicalCommand>,
+    transform: &Transform,
+) -> Result<Vec<CanonicalCommand>, TransformError> {
+    cmds.into_iter()
+        .map(|cmd| match cmd {
+            CanonicalCommand::PlotAbsolute(point) => match transform.apply(point) {
+                Some(point) => Ok(CanonicalCommand::PlotAbsolute(point)),
+                None => Err(TransformError::OutOfRange { point }),
+            },
+            cmd => Ok(cmd),
+        })
+        .collect()
+}
+
+/// The lower left and upper right corners of the lines drawn by canonical commands, or `None` if
+/// they don't draw anything.
+pub fn drawing_bounds(cmds: &[CanonicalCommand]) -> Option<(Point, Point)> {
+    let mut bounds: Option<(Point, Point)> = None;
+    let mut extend = |p: Point| {
+        bounds = Some(match bounds {
+            None => (p, p),
+            Some((min, max)) => (
+                Point {
+                    x: min.x.min(p.x),
+                    y: min.y.min(p.y),
+                },
+                Point {
+                    x: max.x.max(p.x),
+                    y: max.y.max(p.y),
+                },
+            ),
+        })
+    };
+    let mut position = Point { x: 0, y: 0 };
+    let mut pen_down = false;
+    for cmd in cmds {
+        match cmd {
+            CanonicalCommand::PenUp | CanonicalCommand::Initialize => pen_down = false,
+            CanonicalCommand::PenDown => pen_down = true,
+            CanonicalCommand::PlotAbsolute(point) => {
+                if pen_down {
+                    extend(position);
+                    extend(*point);
+                }
+                position = *point;
+            }
+            _ => {}
+        }
+    }
+    bounds
+}
+
+/// The page of a model, shrunk by `margin` plotter units on each side.
+fn page(model: &Model, margin: i32) -> ((f64, f64), (f64, f64)) {
+    let (min, max) = model.hard_clip_limits;
+    let margin = f64::from(margin);
+    (
+        (f64::from(min.x) + margin, f64::from(min.y) + margin),
+        (f64::from(max.x) - margin, f64::from(max.y) - margin),
+    )
+}
+
+fn center((min, max): ((f64, f64), (f64, f64))) -> (f64, f64) {
+    ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0)
+}
+
+fn float_bounds((min, max): (Point, Point)) -> ((f64, f64), (f64, f64)) {
+    (
+        (f64::from(min.x), f64::from(min.y)),
+        (f64::from(max.x), f64::from(max.y)),
+    )
+}
+
+/// A transform that moves `bounds` (from `drawing_bounds`) so that it's centered on a model's
+/// page.
+pub fn center_on_page(bounds: (Point, Point), model: &Model) -> Transform {
+    let (x, y) = center(float_bounds(bounds));
+    let (page_x, page_y) = center(page(model, 0));
+    Transform::translate(page_x - x, page_y - y)
+}
+
+/// A transform that scales `bounds` (from `drawing_bounds`) as large as it can be while keeping
+/// its aspect ratio and staying `margin` plotter units inside of a model's page, and centers it
+/// there.
+pub fn fit_to_page(bounds: (Point, Point), model: &Model, margin: i32) -> Transform {
+    let (min, max) = float_bounds(bounds);
+    let (width, height) = (max.0 - min.0, max.1 - min.1);
+    let page = page(model, margin);
+    let (page_width, page_height) = ((page.1).0 - (page.0).0, (page.1).1 - (page.0).1);
+    // a drawing that's a single line or point can only be scaled along the axes it has a size in
+    let scale = match (width > 0.0, height > 0.0) {
+        (true, true) => (page_width / width).min(page_height / height),
+        (true, false) => page_width / width,
+        (false, true) => page_height / height,
+        (false, false) => 1.0,
+    };
+    let (x, y) = center((min, max));
+    let (page_x, page_y) = center(page);
+    Transform::translate(-x, -y)
+        .then(&Transform::scale(scale, scale))
+        .then(&Transform::translate(page_x, page_y))
+}
+
+/// Rewrites canonical commands to use relative movements (`PR`) rather than absolute ones, as if
+/// the pen started at the origin, so that they can be drawn from wherever the pen happens to be.
+/// The last command is a `PA;`, so that anything after the commands goes back to absolute
+/// movements.
+pub fn to_relative(cmds: Vec<CanonicalCommand>) -> Vec<Command> {
+    let mut out = vec![];
+    let mut position = Point { x: 0, y: 0 };
+    for cmd in cmds {
+        match cmd {
+            CanonicalCommand::PlotAbsolute(point) => {
+                // the difference between two points can be out of range, in which case it has
+                // to be split up
+                let dx = i64::from(point.x) - i64::from(position.x);
+                let dy = i64::from(point.y) - i64::from(position.y);
+                let max = i64::from(i32::MAX);
+                let steps = ((dx.abs().max(dy.abs()) + max - 1) / max).max(1);
+                let mut moves = vec![];
+                for i in 0..steps {
+                    let part = |d: i64| (d * (i + 1) / steps - d * i / steps) as i32;
+                    moves.push(Point {
+                        x: part(dx),
+                        y: part(dy),
+                    });
+                }
+                out.push(Command::PlotRelative(moves));
+                position = point;
+            }
+            cmd => out.push(cmd.into()),
+        }
+    }
+    out.push(Command::PlotAbsolute(vec![]));
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::builtin_models;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_transforms() {
+        assert_eq!(
+            Transform::translate(10.0, -5.0).apply(p(1, 2)),
+            Some(p(11, -3))
+        );
+        assert_eq!(Transform::scale(2.0, 0.5).apply(p(3, 3)), Some(p(6, 2)));
+        assert_eq!(Transform::scale(0.5, 0.5).apply(p(-3, 3)), Some(p(-2, 2)));
+        assert_eq!(Transform::rotate(90.0).apply(p(100, 0)), Some(p(0, 100)));
+        assert_eq!(Transform::rotate(-90.0).apply(p(100, 0)), Some(p(0, -100)));
+        assert_eq!(Transform::rotate(45.0).apply(p(100, 0)), Some(p(71, 71)));
+        assert_eq!(Transform::mirror_x().apply(p(3, 4)), Some(p(3, -4)));
+        assert_eq!(Transform::mirror_y().apply(p(3, 4)), Some(p(-3, 4)));
+        assert_eq!(
+            Transform::rotate(90.0)
+                .then(&Transform::translate(10.0, 0.0))
+                .apply(p(0, 5)),
+            Some(p(5, 0))
+        );
+        assert_eq!(Transform::scale(2.0, 2.0).apply(p(i32::MAX, 0)), None);
+    }
+
+    #[test]
+    fn test_transform_commands() {
+        let cmds = vec![
+            CanonicalCommand::SelectPen(1),
+            CanonicalCommand::PlotAbsolute(p(1, 1)),
+            CanonicalCommand::PenDown,
+            CanonicalCommand::PlotAbsolute(p(2, 1)),
+            CanonicalCommand::PlotAbsolute(p(3, 1)),
+            CanonicalCommand::PenUp,
+        ];
+        // rounding each point on its own keeps the line the length it should be
+        let moved = transform(cmds.clone(), &Transform::scale(1.5, 1.0)).unwrap();
+        assert_eq!(
+            to_relative(moved),
+            vec![
+                Command::SelectPen(1),
+                Command::PlotRelative(vec![p(2, 1)]),
+                Command::PenDown(vec![]),
+                Command::PlotRelative(vec![p(1, 0)]),
+                Command::PlotRelative(vec![p(2, 0)]),
+                Command::PenUp(vec![]),
+                Command::PlotAbsolute(vec![]),
+            ]
+        );
+        assert_eq!(
+            transform(cmds, &Transform::translate(f64::from(i32::MAX), 0.0)),
+            Err(TransformError::OutOfRange { point: p(1, 1) })
+        );
+        assert_eq!(
+            to_relative(vec![
+                CanonicalCommand::PlotAbsolute(p(i32::MIN, 0)),
+                CanonicalCommand::PlotAbsolute(p(i32::MAX, 0)),
+            ])[1],
+            Command::PlotRelative(vec![p(1431655765, 0); 3])
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let model = &builtin_models()[0];
+        let cmds = vec![
+            CanonicalCommand::PlotAbsolute(p(-500, -500)),
+            CanonicalCommand::PenDown,
+            CanonicalCommand::PlotAbsolute(p(500, -500)),
+            CanonicalCommand::PlotAbsolute(p(500, 0)),
+            CanonicalCommand::PenUp,
+            CanonicalCommand::PlotAbsolute(p(5000, 5000)),
+        ];
+        let bounds = drawing_bounds(&cmds).unwrap();
+        assert_eq!(bounds, (p(-500, -500), p(500, 0)));
+        assert_eq!(drawing_bounds(&cmds[..2]), None);
+
+        let centered = center_on_page(bounds, model);
+        assert_eq!(centered.apply(p(-500, -500)), Some(p(4650, 3575)));
+        assert_eq!(centered.apply(p(500, 0)), Some(p(5650, 4075)));
+
+        // the width is what limits it
+        let fitted = fit_to_page(bounds, model, 150);
+        assert_eq!(fitted.apply(p(-500, -500)), Some(p(150, 1325)));
+        assert_eq!(fitted.apply(p(500, 0)), Some(p(10150, 6325)));
+    }
+}
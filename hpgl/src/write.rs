@@ -0,0 +1,316 @@
+use crate::command::{CharacterStroke, Command, ETX};
+use std::io::{self, Write};
+
+/// How `CommandWriter` lays out the commands it writes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Every command is written back to back, with nothing in between.
+    Compact,
+    /// Every command is followed by a newline.
+    OnePerLine,
+}
+
+/// Writes commands as HPGL, keeping track of the label terminator so that anything it writes will
+/// be parsed back into the same commands.
+///
+/// Commands that can't be written in a way that would be parsed back the same - a label that
+/// contains its own terminator, a `UC` move of 99 grid units, or a `DT` or `SM` character that's a
+/// `;`, whitespace or isn't ascii - are rejected with an `InvalidInput` error, and nothing is
+/// written.
+pub struct CommandWriter<W> {
+    writer: W,
+    style: Style,
+    terminator: char,
+}
+
+impl<W: Write> CommandWriter<W> {
+    pub fn new(writer: W, style: Style) -> CommandWriter<W> {
+        CommandWriter {
+            writer,
+            style,
+            terminator: ETX,
+        }
+    }
+
+    pub fn write_command(&mut self, cmd: &Command) -> io::Result<()> {
+        self.check(cmd)?;
+        match cmd {
+            Command::Label(text) => write!(self.writer, "LB{}{}", text, self.terminator)?,
+            cmd => write!(self.writer, "{}", cmd)?,
+        }
+        if self.style == Style::OnePerLine {
+            writeln!(self.writer)?;
+        }
+        match cmd {
+            Command::DefineTerminator(terminator) => self.terminator = terminator.unwrap_or(ETX),
+            Command::Initialize | Command::Default => self.terminator = ETX,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn write_commands<'a, I: IntoIterator<Item = &'a Command>>(
+        &mut self,
+        cmds: I,
+    ) -> io::Result<()> {
+        for cmd in cmds {
+            self.write_command(cmd)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn check(&self, cmd: &Command) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        match cmd {
+            Command::Label(text) if text.contains(self.terminator) => invalid(format!(
+                "label {:?} contains the label terminator {:?}",
+                text, self.terminator
+            )),
+            // whitespace would be trimmed off when it's read back in, leaving no parameter at all
+            Command::DefineTerminator(Some(c)) | Command::SymbolMode(Some(c))
+                if *c == ';' || !c.is_ascii() || c.is_whitespace() =>
+            {
+                invalid(format!(
+                    "{:?} can't be used as a parameter to `{}`",
+                    c,
+                    cmd.mnemonic()
+                ))
+            }
+            Command::UserDefinedCharacter(strokes)
+                if strokes.iter().any(|s| {
+                    matches!(
+                        s,
+                        CharacterStroke::Move(99, _) | CharacterStroke::Move(-99, _)
+                    )
+                }) =>
+            {
+                invalid(
+                    "a `UC` move of 99 grid units would be read as a pen up or down".to_string(),
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes a list of commands as a string of HPGL.
+pub fn serialize(cmds: &[Command], style: Style) -> io::Result<String> {
+    let mut writer = CommandWriter::new(vec![], style);
+    writer.write_commands(cmds)?;
+    // everything we write is built from strings, so it's valid utf-8
+    Ok(String::from_utf8(writer.into_inner()).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::{InputMask, Scaling};
+    use crate::{parse_commands, Point};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_styles() {
+        let cmds = vec![
+            Command::SelectPen(1),
+            Command::PlotAbsolute(vec![Point { x: 10, y: 20 }]),
+            Command::Label("hi".to_string()),
+        ];
+        assert_eq!(
+            serialize(&cmds, Style::Compact).unwrap(),
+            "SP1;PA10,20;LBhi\x03"
+        );
+        assert_eq!(
+            serialize(&cmds, Style::OnePerLine).unwrap(),
+            "SP1;\nPA10,20;\nLBhi\x03\n"
+        );
+    }
+
+    #[test]
+    fn test_terminator() {
+        let cmds = vec![
+            Command::Label("a".to_string()),
+            Command::DefineTerminator(Some('#')),
+            Command::Label("b".to_string()),
+            Command::Initialize,
+            Command::Label("c".to_string()),
+        ];
+        assert_eq!(
+            serialize(&cmds, Style::Compact).unwrap(),
+            "LBa\x03DT#;LBb#IN;LBc\x03"
+        );
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        let errors = vec![
+            vec![Command::Label("a\x03".to_string())],
+            vec![
+                Command::DefineTerminator(Some('#')),
+                Command::Label("#".to_string()),
+            ],
+            vec![Command::DefineTerminator(Some(';'))],
+            vec![Command::SymbolMode(Some('é'))],
+            vec![Command::UserDefinedCharacter(vec![CharacterStroke::Move(
+                -99, 0,
+            )])],
+        ];
+        for cmds in errors {
+            assert_eq!(
+                serialize(&cmds, Style::Compact).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+
+    fn point() -> impl Strategy<Value = Point> {
+        (any::<i32>(), any::<i32>()).prop_map(|(x, y)| Point { x, y })
+    }
+
+    fn real() -> impl Strategy<Value = f64> {
+        prop_oneof![
+            prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO,
+            // short decimals are what real files mostly have in them
+            (-100_000i32..100_000).prop_map(|x| f64::from(x) / 100.0),
+        ]
+    }
+
+    fn pair() -> impl Strategy<Value = (f64, f64)> {
+        (real(), real())
+    }
+
+    // these can't be label terminators, so that labels can't end up containing their terminator
+    const TERMINATORS: &[char] = &['\x03', '*', '#', '~'];
+
+    fn label_text() -> impl Strategy<Value = String> {
+        "[ -\"$-)+-}]*"
+    }
+
+    fn stroke() -> impl Strategy<Value = CharacterStroke> {
+        prop_oneof![
+            Just(CharacterStroke::PenDown),
+            Just(CharacterStroke::PenUp),
+            (
+                any::<i32>().prop_filter("99 is pen control", |x| *x != 99 && *x != -99),
+                any::<i32>()
+            )
+                .prop_map(|(x, y)| CharacterStroke::Move(x, y)),
+        ]
+    }
+
+    fn input_mask() -> impl Strategy<Value = InputMask> {
+        prop_oneof![
+            any::<u8>().prop_map(|error| InputMask {
+                error,
+                service_request: None,
+                parallel_poll: None,
+            }),
+            (any::<u8>(), any::<u8>(), proptest::option::of(any::<u8>())).prop_map(
+                |(error, service_request, parallel_poll)| InputMask {
+                    error,
+                    service_request: Some(service_request),
+                    parallel_poll,
+                }
+            ),
+        ]
+    }
+
+    fn command() -> impl Strategy<Value = Command> {
+        let points = || prop::collection::vec(point(), 0..4);
+        prop_oneof![
+            points().prop_map(Command::PenUp),
+            points().prop_map(Command::PenDown),
+            points().prop_map(Command::PlotAbsolute),
+            points().prop_map(Command::PlotRelative),
+            any::<u8>().prop_map(Command::SelectPen),
+            (any::<i32>(), proptest::option::of(real()))
+                .prop_map(|(radius, chord_angle)| Command::Circle(radius, chord_angle)),
+            (point(), real(), proptest::option::of(real())).prop_map(
+                |(center, angle, chord_angle)| Command::ArcAbsolute(center, angle, chord_angle)
+            ),
+            (point(), real(), proptest::option::of(real())).prop_map(
+                |(center, angle, chord_angle)| Command::ArcRelative(center, angle, chord_angle)
+            ),
+            Just(Command::Initialize),
+            Just(Command::Default),
+            proptest::option::of((point(), proptest::option::of(point())))
+                .prop_map(Command::InputP1P2),
+            proptest::option::of((point(), point())).prop_map(Command::InputWindow),
+            proptest::option::of((any::<i32>(), any::<i32>(), any::<i32>(), any::<i32>()))
+                .prop_map(
+                    |s| Command::Scale(s.map(|(x_min, x_max, y_min, y_max)| Scaling {
+                        x_min,
+                        x_max,
+                        y_min,
+                        y_max
+                    }))
+                ),
+            proptest::option::of(real()).prop_map(Command::VelocitySelect),
+            proptest::option::of((any::<u8>(), proptest::option::of(real())))
+                .prop_map(Command::LineType),
+            proptest::option::of((real(), proptest::option::of(real())))
+                .prop_map(Command::TickLength),
+            Just(Command::XTick),
+            Just(Command::YTick),
+            label_text().prop_map(Command::Label),
+            proptest::option::of(proptest::sample::select(TERMINATORS))
+                .prop_map(Command::DefineTerminator),
+            proptest::option::of(any::<u8>()).prop_map(Command::DesignateAlternateSet),
+            proptest::option::of(any::<u8>()).prop_map(Command::DesignateStandardSet),
+            Just(Command::SelectAlternateSet),
+            Just(Command::SelectStandardSet),
+            proptest::option::of(pair()).prop_map(Command::CharacterPlot),
+            proptest::option::of(pair()).prop_map(Command::AbsoluteDirection),
+            proptest::option::of(pair()).prop_map(Command::RelativeDirection),
+            proptest::option::of(pair()).prop_map(Command::AbsoluteCharacterSize),
+            proptest::option::of(pair()).prop_map(Command::RelativeCharacterSize),
+            proptest::option::of(real()).prop_map(Command::CharacterSlant),
+            proptest::option::of(proptest::char::range('\0', '\x7f')).prop_map(Command::SymbolMode),
+            prop::collection::vec(stroke(), 0..6).prop_map(Command::UserDefinedCharacter),
+            proptest::option::of(input_mask()).prop_map(Command::InputMask),
+            Just(Command::DigitizeClear),
+            Just(Command::DigitizePoint),
+            Just(Command::OutputActualPosition),
+            Just(Command::OutputCommandedPosition),
+            Just(Command::OutputDigitizedPoint),
+            Just(Command::OutputError),
+            Just(Command::OutputFactors),
+            Just(Command::OutputHardClipLimits),
+            Just(Command::OutputIdentification),
+            Just(Command::OutputOptions),
+            Just(Command::OutputP1P2),
+            Just(Command::OutputStatus),
+            Just(Command::OutputWindow),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(cmds in prop::collection::vec(command(), 0..40)) {
+            for style in &[Style::Compact, Style::OnePerLine] {
+                match serialize(&cmds, *style) {
+                    Ok(hpgl) => prop_assert_eq!(parse_commands(hpgl), Ok(cmds.clone())),
+                    // the only commands that can't be written are ones that wouldn't read back
+                    Err(_) => prop_assert!(cmds.iter().any(|cmd| matches!(
+                        cmd,
+                        Command::SymbolMode(Some(c)) if *c == ';' || c.is_whitespace()
+                    ))),
+                }
+            }
+        }
+
+        #[test]
+        fn test_round_trip_parameter_chars(c in proptest::char::range('\0', '\x7f')) {
+            for cmd in [Command::DefineTerminator(Some(c)), Command::SymbolMode(Some(c))] {
+                let cmds = vec![cmd, Command::PenUp(vec![])];
+                match serialize(&cmds, Style::Compact) {
+                    Ok(hpgl) => prop_assert_eq!(parse_commands(hpgl), Ok(cmds)),
+                    Err(_) => prop_assert!(c == ';' || c.is_whitespace()),
+                }
+            }
+        }
+    }
+}
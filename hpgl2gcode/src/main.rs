@@ -18,64 +18,99 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use hpgl::{parse_commands, Command, Point};
-use std::fs::File;
-use std::io::prelude::*;
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CommandReader, Event, Interpreter, Point};
 use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-fn plot_points(points: Vec<Point>, xscale: f64, yscale: f64) {
-    for point in points {
-        println!(
-            "G1 X{} Y{}",
-            (point.x as f64) * xscale,
-            (point.y as f64) * yscale
-        );
-    }
+fn plot_point(point: Point, xscale: f64, yscale: f64) {
+    println!(
+        "G1 X{} Y{}",
+        (point.x as f64) * xscale,
+        (point.y as f64) * yscale
+    );
+}
+
+fn set_pen(down: bool) -> bool {
+    println!("{}", if down { "M106" } else { "M107" });
+    println!("G4 P100");
+    down
 }
 
 #[derive(Debug, StructOpt)]
 struct Args {
+    #[structopt(help = "HPGL file to convert, or '-' to read from stdin")]
     file: PathBuf,
     #[structopt(long = "xscale", short = "x", default_value = "0.076")]
     xscale: f64,
     #[structopt(long = "yscale", short = "y", default_value = "0.076")]
     yscale: f64,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::from_args();
-    let mut file = File::open(args.file)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let cmds = parse_commands(contents).unwrap();
+    let cmds = CommandReader::open(&args.file)?;
+    let mut had_errors = false;
+    let mut interpreter = Interpreter::new();
+    interpreter.set_expand_line_types(true);
+    interpreter.set_expand_labels(true);
+    interpreter.set_hard_clip_limits(Some(args.model.hard_clip_limits));
+    let mut pen_down = false;
+
+    // the interpreter resolves relative moves, so everything we send is absolute
+    println!("G90");
 
     for cmd in cmds {
-        match cmd {
-            Command::PenUp(points) => {
-                println!("G90");
-                println!("M107");
-                println!("G4 P100");
-                plot_points(points, args.xscale, args.yscale);
-            }
-            Command::PenDown(points) => {
-                println!("G90");
-                println!("M106");
-                println!("G4 P100");
-                plot_points(points, args.xscale, args.yscale);
+        let cmd = match cmd {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e.diagnostic(&args.file));
+                had_errors = true;
+                continue;
             }
-            Command::PlotAbsolute(points) => {
-                println!("G90");
-                plot_points(points, args.xscale, args.yscale);
+        };
+        // where the pen is as of each event
+        let mut position = interpreter.state().position;
+        let events = match interpreter.execute(cmd) {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                had_errors = true;
+                break;
             }
-            Command::PlotRelative(points) => {
-                println!("G91");
-                plot_points(points, args.xscale, args.yscale);
+        };
+        for event in events {
+            match event {
+                Event::PenUp => pen_down = set_pen(false),
+                // outside of the window or the hard clip limits, the plotter doesn't put the pen
+                // down until a line comes back inside
+                Event::PenDown if interpreter.draws_at(position) => pen_down = set_pen(true),
+                Event::PenDown => {}
+                // the plotter lifts the pen when a line goes outside of the window, so the pen
+                // can be down for a travel, or up for a stroke
+                Event::Travel { to, .. } => {
+                    position = to;
+                    if pen_down {
+                        pen_down = set_pen(false);
+                    }
+                    plot_point(to, args.xscale, args.yscale);
+                }
+                Event::Stroke { to, .. } => {
+                    position = to;
+                    if !pen_down {
+                        pen_down = set_pen(true);
+                    }
+                    plot_point(to, args.xscale, args.yscale);
+                }
+                _ => {}
             }
-            Command::SelectPen(_) => {}
-            Command::Initialize => {}
         }
     }
+    if had_errors {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,50 @@
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CommandReader, Linter, Severity};
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(help = "HPGL file to check, or '-' to read from stdin")]
+    file: PathBuf,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
+    #[structopt(long, help = "Only report errors, not warnings")]
+    quiet: bool,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::from_args();
+    let cmds = CommandReader::open(&args.file)?.spanned();
+    let mut linter = Linter::new(args.model);
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for cmd in cmds {
+        let (cmd, span) = match cmd {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e.diagnostic(&args.file));
+                errors += 1;
+                continue;
+            }
+        };
+        for lint in linter.check(cmd, span) {
+            match lint.severity() {
+                Severity::Error => errors += 1,
+                Severity::Warning if args.quiet => continue,
+                Severity::Warning => warnings += 1,
+            }
+            eprintln!("{}", lint.diagnostic(&args.file));
+        }
+    }
+
+    if errors > 0 || warnings > 0 {
+        eprintln!("{} error(s), {} warning(s)", errors, warnings);
+    }
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
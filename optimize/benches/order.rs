@@ -0,0 +1,40 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use hpgl::{Point, Polyline};
+use optimize::order::order_shapes;
+
+/// A drawing of `n` shapes scattered over a 7475A's A3 page - mostly stipple dots, with some
+/// short lines and small triangles - using a xorshift generator so it's the same every time.
+fn drawing(n: usize) -> Vec<Polyline> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move |max: i32| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % max as u64) as i32
+    };
+    (0..n)
+        .map(|i| {
+            let p = Point {
+                x: random(16640),
+                y: random(10365),
+            };
+            let q = Point {
+                x: p.x + random(200),
+                y: p.y + random(200),
+            };
+            match i % 10 {
+                0 => vec![p, q],
+                1 => vec![p, q, Point { x: q.x, y: p.y }, p],
+                _ => vec![p],
+            }
+        })
+        .collect()
+}
+
+fn order_100k(b: &mut Bencher) {
+    let shapes = drawing(100_000);
+    b.iter(|| order_shapes(shapes.clone(), Point { x: 0, y: 0 }));
+}
+
+benchmark_group!(benches, order_100k);
+benchmark_main!(benches);
@@ -0,0 +1,243 @@
+use crate::order::distance;
+use hpgl::{Point, Polyline};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The infinite line a segment is on, as the smallest whole number step `(a, b)` along it
+/// (pointing right, or up if it's vertical) and `b x - a y`, which is the same for every point
+/// on it. Segments on the same line get the same key, so looking up the ones a segment might
+/// overlap is a hash lookup.
+type LineKey = (i64, i64, i64);
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// A segment, as the line it's on and how far along that line each end is.
+struct Segment {
+    key: LineKey,
+    from: i64,
+    to: i64,
+    start: Point,
+}
+
+impl Segment {
+    /// `None` if the segment doesn't go anywhere.
+    fn new(p: Point, q: Point) -> Option<Segment> {
+        let dx = i64::from(q.x) - i64::from(p.x);
+        let dy = i64::from(q.y) - i64::from(p.y);
+        let g = gcd(dx, dy);
+        if g == 0 {
+            return None;
+        }
+        let (mut a, mut b) = (dx / g, dy / g);
+        if a < 0 || (a == 0 && b < 0) {
+            a = -a;
+            b = -b;
+        }
+        let along = |p: Point| a * i64::from(p.x) + b * i64::from(p.y);
+        Some(Segment {
+            key: (a, b, b * i64::from(p.x) - a * i64::from(p.y)),
+            from: along(p),
+            to: along(q),
+            start: p,
+        })
+    }
+
+    /// The point `t` along the line, which has to be a whole number point on the segment's line.
+    fn point(&self, t: i64) -> Point {
+        let (a, b, _) = self.key;
+        let steps = (t - self.from) / (a * a + b * b);
+        Point {
+            x: (i64::from(self.start.x) + steps * a) as i32,
+            y: (i64::from(self.start.y) + steps * b) as i32,
+        }
+    }
+}
+
+/// The parts of each line that have been drawn, as a map from where each part starts to where
+/// it ends, with none of them overlapping or touching.
+#[derive(Default)]
+struct Drawn(HashMap<LineKey, BTreeMap<i64, i64>>);
+
+impl Drawn {
+    /// The parts of `from..to` that haven't been drawn yet, in order from `from` to `to`.
+    fn undrawn(&self, key: LineKey, from: i64, to: i64) -> Vec<(i64, i64)> {
+        let (lo, hi) = (from.min(to), from.max(to));
+        let mut gaps = vec![];
+        let mut position = lo;
+        if let Some(drawn) = self.0.get(&key) {
+            let first = drawn
+                .range(..=lo)
+                .next_back()
+                .map_or(lo, |(start, _)| *start);
+            for (start, end) in drawn.range(first..hi) {
+                if *start > position {
+                    gaps.push((position, *start));
+                }
+                position = position.max(*end);
+            }
+        }
+        if position < hi {
+            gaps.push((position, hi));
+        }
+        if from > to {
+            gaps.reverse();
+            for gap in &mut gaps {
+                *gap = (gap.1, gap.0);
+            }
+        }
+        gaps
+    }
+
+    fn draw(&mut self, key: LineKey, from: i64, to: i64) {
+        let drawn = self.0.entry(key).or_default();
+        let (mut lo, mut hi) = (from.min(to), from.max(to));
+        let first = drawn
+            .range(..=lo)
+            .next_back()
+            .map_or(lo, |(start, _)| *start);
+        let overlapping: Vec<(i64, i64)> = drawn
+            .range(first..=hi)
+            .filter(|(_, end)| **end >= lo)
+            .map(|(start, end)| (*start, *end))
+            .collect();
+        for (start, end) in overlapping {
+            drawn.remove(&start);
+            lo = lo.min(start);
+            hi = hi.max(end);
+        }
+        drawn.insert(lo, hi);
+    }
+}
+
+/// Removes the parts of lines that have already been drawn by an earlier line, along with dots
+/// that have already been drawn, splitting lines up where they have to be. Lines are only
+/// compared with ones on the exact same line, so ones that are drawn almost on top of each
+/// other are both kept.
+///
+/// With `keep_retraced`, a line that goes back over itself (which is usually done on purpose, to
+/// make it darker) keeps doing that, and only parts that were drawn by a different line are
+/// removed.
+///
+/// Returns the remaining lines, and how much less drawing that is, in plotter units.
+pub fn dedupe(shapes: Vec<Polyline>, keep_retraced: bool) -> (Vec<Polyline>, f64) {
+    let mut drawn = Drawn::default();
+    let mut dots = HashSet::new();
+    let mut removed = 0.0;
+    let mut out = vec![];
+
+    for shape in shapes {
+        let segments: Vec<Segment> = shape
+            .windows(2)
+            .filter_map(|pair| Segment::new(pair[0], pair[1]))
+            .collect();
+        if segments.is_empty() {
+            if let Some(dot) = shape.first() {
+                if dots.insert((dot.x, dot.y)) {
+                    out.push(vec![*dot]);
+                }
+            }
+            continue;
+        }
+
+        let mut current: Option<Polyline> = None;
+        for segment in &segments {
+            for (from, to) in drawn.undrawn(segment.key, segment.from, segment.to) {
+                let (from, to) = (segment.point(from), segment.point(to));
+                match &mut current {
+                    Some(current) if current.last() == Some(&from) => current.push(to),
+                    _ => out.extend(current.replace(vec![from, to])),
+                }
+            }
+            if !keep_retraced {
+                drawn.draw(segment.key, segment.from, segment.to);
+            }
+        }
+        out.extend(current);
+        if keep_retraced {
+            for segment in &segments {
+                drawn.draw(segment.key, segment.from, segment.to);
+            }
+        }
+        removed += length(&shape);
+    }
+    for shape in &out {
+        removed -= length(shape);
+    }
+    (out, removed)
+}
+
+fn length(shape: &[Point]) -> f64 {
+    shape
+        .windows(2)
+        .map(|pair| distance(&pair[0], &pair[1]))
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let shapes = vec![
+            vec![p(0, 0), p(100, 0), p(100, 100)],
+            // the same, backwards
+            vec![p(100, 100), p(100, 0), p(0, 0)],
+            // overlaps the first line in the middle, and sticks out past both of its ends
+            vec![p(-50, 0), p(150, 0)],
+            // on the same line, but not overlapping anything
+            vec![p(200, 0), p(300, 0)],
+            // a diagonal line, half of which is drawn again
+            vec![p(0, 0), p(30, 60)],
+            vec![p(15, 30), p(45, 90), p(45, 0)],
+            vec![p(5, 5)],
+            vec![p(5, 5)],
+        ];
+        let (out, removed) = dedupe(shapes, false);
+        assert_eq!(
+            out,
+            vec![
+                vec![p(0, 0), p(100, 0), p(100, 100)],
+                vec![p(-50, 0), p(0, 0)],
+                vec![p(100, 0), p(150, 0)],
+                vec![p(200, 0), p(300, 0)],
+                vec![p(0, 0), p(30, 60)],
+                vec![p(30, 60), p(45, 90), p(45, 0)],
+                vec![p(5, 5)],
+            ]
+        );
+        assert!((removed - (200.0 + 100.0 + distance(&p(15, 30), &p(30, 60)))).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_retraced() {
+        let shapes = vec![
+            // goes back over itself on purpose
+            vec![p(0, 0), p(100, 0), p(0, 0)],
+            vec![p(50, 0), p(200, 0)],
+        ];
+        let (out, _) = dedupe(shapes.clone(), false);
+        assert_eq!(
+            out,
+            vec![vec![p(0, 0), p(100, 0)], vec![p(100, 0), p(200, 0)]]
+        );
+        let (out, removed) = dedupe(shapes, true);
+        assert_eq!(
+            out,
+            vec![
+                vec![p(0, 0), p(100, 0), p(0, 0)],
+                vec![p(100, 0), p(200, 0)]
+            ]
+        );
+        assert_eq!(removed, 50.0);
+    }
+}
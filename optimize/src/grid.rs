@@ -0,0 +1,190 @@
+use hpgl::Point;
+
+/// A uniform grid of points, each with a value attached, for finding the nearest point to
+/// another one without looking at all of them.
+///
+/// Points can be removed, but not added once the grid is built, since the size of the grid is
+/// picked from where the points are.
+pub struct Grid<T> {
+    min: Point,
+    cell_size: i64,
+    width: i64,
+    height: i64,
+    cells: Vec<Vec<(Point, T)>>,
+    len: usize,
+}
+
+/// The (squared) distance between two points, which is exact, unlike `f64::hypot`.
+pub fn distance_squared(a: Point, b: Point) -> i64 {
+    let dx = i64::from(a.x) - i64::from(b.x);
+    let dy = i64::from(a.y) - i64::from(b.y);
+    dx * dx + dy * dy
+}
+
+impl<T: Copy + Ord> Grid<T> {
+    pub fn new(points: Vec<(Point, T)>) -> Grid<T> {
+        let min = Point {
+            x: points.iter().map(|(p, _)| p.x).min().unwrap_or(0),
+            y: points.iter().map(|(p, _)| p.y).min().unwrap_or(0),
+        };
+        let max = Point {
+            x: points.iter().map(|(p, _)| p.x).max().unwrap_or(0),
+            y: points.iter().map(|(p, _)| p.y).max().unwrap_or(0),
+        };
+        let span_x = i64::from(max.x) - i64::from(min.x) + 1;
+        let span_y = i64::from(max.y) - i64::from(min.y) + 1;
+        // about two points to a cell, if they're spread out evenly. the cells are square, so for
+        // long, thin drawings they're made big enough that there aren't more cells along the long
+        // side than that either, or there'd be far more cells than points
+        let cells = (points.len() as f64 / 2.0).max(1.0);
+        let cell_size = (span_x as f64 * span_y as f64 / cells)
+            .sqrt()
+            .max(span_x.max(span_y) as f64 / cells)
+            .ceil() as i64;
+        let cell_size = cell_size.max(1);
+        let mut grid = Grid {
+            min,
+            cell_size,
+            width: (span_x + cell_size - 1) / cell_size,
+            height: (span_y + cell_size - 1) / cell_size,
+            cells: vec![],
+            len: points.len(),
+        };
+        grid.cells = vec![vec![]; (grid.width * grid.height) as usize];
+        for (point, value) in points {
+            let cell = grid.cell_index(grid.cell(point));
+            grid.cells[cell].push((point, value));
+        }
+        grid
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes the point with the given value, which must be at `point`.
+    pub fn remove(&mut self, point: Point, value: T) {
+        let cell = self.cell_index(self.cell(point));
+        let before = self.cells[cell].len();
+        self.cells[cell].retain(|(_, v)| *v != value);
+        self.len -= before - self.cells[cell].len();
+    }
+
+    /// Finds the point closest to `to`, picking the one with the smallest value if there's a
+    /// tie.
+    pub fn nearest(&self, to: Point) -> Option<(Point, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (qx, qy) = self.cell(to);
+        // the rings of cells around `to` that overlap the grid
+        let outside = |q: i64, size: i64| (-q).max(q - (size - 1)).max(0);
+        let first_ring = outside(qx, self.width).max(outside(qy, self.height));
+        let last_ring = qx
+            .max(self.width - 1 - qx)
+            .max(qy)
+            .max(self.height - 1 - qy);
+
+        let mut best: Option<(i64, T, Point)> = None;
+        for ring in first_ring..=last_ring {
+            // everything in this ring is at least this far away
+            let bound = (ring - 1).max(0) * self.cell_size;
+            if let Some((distance, _, _)) = best {
+                if distance < bound * bound {
+                    break;
+                }
+            }
+            for cy in (qy - ring).max(0)..=(qy + ring).min(self.height - 1) {
+                let mut check = |cx: i64| {
+                    if cx < 0 || cx >= self.width {
+                        return;
+                    }
+                    for (point, value) in &self.cells[self.cell_index((cx, cy))] {
+                        let candidate = (distance_squared(*point, to), *value, *point);
+                        let better = match best {
+                            None => true,
+                            Some((d, v, _)) => (candidate.0, candidate.1) < (d, v),
+                        };
+                        if better {
+                            best = Some(candidate);
+                        }
+                    }
+                };
+                if cy == qy - ring || cy == qy + ring {
+                    for cx in (qx - ring).max(0)..=(qx + ring).min(self.width - 1) {
+                        check(cx);
+                    }
+                } else {
+                    check(qx - ring);
+                    check(qx + ring);
+                }
+            }
+        }
+        best.map(|(_, value, point)| (point, value))
+    }
+
+    /// The cell a point is in, which might be outside of the grid.
+    fn cell(&self, point: Point) -> (i64, i64) {
+        (
+            (i64::from(point.x) - i64::from(self.min.x)).div_euclid(self.cell_size),
+            (i64::from(point.y) - i64::from(self.min.y)).div_euclid(self.cell_size),
+        )
+    }
+
+    fn cell_index(&self, (cx, cy): (i64, i64)) -> usize {
+        (cy * self.width + cx) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_grid() {
+        let points: Vec<(Point, usize)> = (0..100)
+            .map(|i| (p((i * 37) % 1000, (i * 91) % 700), i as usize))
+            .collect();
+        let mut grid = Grid::new(points.clone());
+        let queries = [p(0, 0), p(500, 350), p(-5000, 20000), p(999, 1)];
+        for (i, (point, value)) in points.iter().enumerate() {
+            for query in &queries {
+                let expected = points[i..]
+                    .iter()
+                    .min_by_key(|(point, value)| (distance_squared(*point, *query), *value))
+                    .copied();
+                assert_eq!(grid.nearest(*query), expected);
+            }
+            grid.remove(*point, *value);
+        }
+        assert!(grid.is_empty());
+        assert_eq!(grid.nearest(p(0, 0)), None);
+    }
+
+    #[test]
+    fn test_ties() {
+        let grid = Grid::new(vec![(p(10, 0), 2), (p(-10, 0), 1), (p(0, 10), 3)]);
+        assert_eq!(grid.nearest(p(0, 0)), Some((p(-10, 0), 1)));
+    }
+
+    #[test]
+    fn test_long_and_thin() {
+        // points along a line all the way across the coordinate range, which would need
+        // millions of cells if they were sized by area alone
+        let points: Vec<(Point, usize)> = (0..1000)
+            .map(|i| {
+                (
+                    p((i64::from(i32::MIN) + i * 4_000_000) as i32, 0),
+                    i as usize,
+                )
+            })
+            .collect();
+        let grid = Grid::new(points);
+        assert!(grid.cells.len() <= 3 * 1000);
+        assert_eq!(grid.nearest(p(1_000_000, 5)), Some((p(516_352, 0), 537)));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::order::distance;
+use hpgl::{Point, Polyline};
+use std::time::{Duration, Instant};
+
+/// How long `improve` can keep going for. It stops early if there's nothing left to improve.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Budget {
+    /// How many passes over the whole order it can make, or `None` for as many as it takes.
+    pub passes: Option<usize>,
+    /// How long it can take, or `None` for as long as it takes. Where it stops depends on how
+    /// fast the computer is, so the result can be different from run to run.
+    pub time: Option<Duration>,
+}
+
+fn start(shape: &[Point]) -> Point {
+    shape[0]
+}
+
+fn end(shape: &[Point]) -> Point {
+    shape[shape.len() - 1]
+}
+
+/// Refines an order of shapes (like the one `order_shapes` picks), starting with the pen at
+/// `from`, using 2-opt: wherever drawing a run of shapes backwards - in the opposite order,
+/// with each shape drawn backwards too - makes for less travel, it's drawn that way. A run of a
+/// single shape is just that shape drawn backwards.
+///
+/// This gets rid of most of the places where the pen's path crosses itself, which nearest
+/// neighbour ordering leaves lots of, but each pass takes n^2 time, so it needs a `Budget` for
+/// big drawings. Returns how much less the pen travels afterwards.
+///
+/// The shapes can't be empty.
+pub fn improve(shapes: &mut [Polyline], from: Point, budget: Budget) -> f64 {
+    let started = Instant::now();
+    let out_of_time = || match budget.time {
+        Some(time) => started.elapsed() >= time,
+        None => false,
+    };
+    let mut saved = 0.0;
+    let mut passes = 0;
+    while budget.passes != Some(passes) {
+        passes += 1;
+        let mut improved = false;
+        for i in 0..shapes.len() {
+            if out_of_time() {
+                return saved;
+            }
+            let before = if i == 0 { from } else { end(&shapes[i - 1]) };
+            for j in i..shapes.len() {
+                // reversing shapes i..=j only changes how the pen gets to the run and away from
+                // it, since the moves within it are the same, just backwards
+                let after = shapes.get(j + 1).map(|shape| start(shape));
+                let old = distance(&before, &start(&shapes[i]))
+                    + after.map_or(0.0, |after| distance(&end(&shapes[j]), &after));
+                let new = distance(&before, &end(&shapes[j]))
+                    + after.map_or(0.0, |after| distance(&start(&shapes[i]), &after));
+                // a little slack, so rounding can't make it go back and forth forever
+                if new < old - 1e-6 {
+                    shapes[i..=j].reverse();
+                    for shape in &mut shapes[i..=j] {
+                        shape.reverse();
+                    }
+                    saved += old - new;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    saved
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::order::travel;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_flip() {
+        let mut shapes = vec![vec![p(0, 0), p(100, 0)], vec![p(100, 100), p(100, 0)]];
+        let saved = improve(&mut shapes, p(0, 0), Budget::default());
+        assert_eq!(saved, 100.0);
+        assert_eq!(
+            shapes,
+            vec![vec![p(0, 0), p(100, 0)], vec![p(100, 0), p(100, 100)]]
+        );
+    }
+
+    #[test]
+    fn test_crossing() {
+        // the corners of a square, visited in an order that crosses over itself
+        let original = vec![
+            vec![p(0, 0)],
+            vec![p(1000, 1000)],
+            vec![p(1000, 0)],
+            vec![p(0, 1000)],
+        ];
+        let start = p(0, 0);
+        let mut shapes = original.clone();
+        let saved = improve(&mut shapes, start, Budget::default());
+        assert_eq!(
+            shapes,
+            vec![
+                vec![p(0, 0)],
+                vec![p(1000, 0)],
+                vec![p(1000, 1000)],
+                vec![p(0, 1000)],
+            ]
+        );
+        assert_eq!(travel(start, &shapes), 3000.0);
+        assert!((travel(start, &original) - saved - travel(start, &shapes)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_budget() {
+        let original: Vec<Polyline> = (0..50)
+            .map(|i| vec![p((i * 7919) % 1000, (i * 104_729) % 1000)])
+            .collect();
+        let start = p(0, 0);
+        let mut shapes = original.clone();
+        let budget = Budget {
+            passes: Some(0),
+            time: None,
+        };
+        assert_eq!(improve(&mut shapes, start, budget), 0.0);
+        assert_eq!(shapes, original);
+
+        let budget = Budget {
+            passes: Some(1),
+            time: None,
+        };
+        let saved = improve(&mut shapes, start, budget);
+        assert!(saved > 0.0);
+        assert!((travel(start, &original) - saved - travel(start, &shapes)).abs() < 1e-6);
+        // once it's done, there's nothing left to improve
+        improve(&mut shapes, start, Budget::default());
+        assert_eq!(improve(&mut shapes, start, Budget::default()), 0.0);
+    }
+}
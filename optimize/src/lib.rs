@@ -0,0 +1,8 @@
+//! The parts of `optimize` that don't need the command line, so that they can be benchmarked.
+
+pub mod dedupe;
+mod grid;
+pub mod improve;
+pub mod merge;
+pub mod order;
+pub mod pens;
@@ -1,177 +1,257 @@
-#![feature(or_patterns)]
-use hpgl::{parse_commands, canonicalize, canonical_commands_to_string, CanonicalCommand, Command, Point};
-use std::collections::HashMap;
-use std::fs::read_to_string;
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{
+    simplify, CanonicalizeOptions, ClipRegion, Command, CommandReader, CommandWriter, Drawing,
+    Point, Stats, Style, UNITS_PER_CM,
+};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use optimize::dedupe::dedupe;
+use optimize::improve::{improve, Budget};
+use optimize::merge::merge;
+use optimize::order::order_shapes;
+use optimize::pens::{Palette, PenOrder};
 
 // possible optimizations:
-// * remove duplicate PU and PD commands (done)
+// * remove duplicate PU and PD commands (done, by writing out a `Drawing`)
 // * coalesce multiple PA, PU, and PD commands into one (is a superset of the above)
-// * merge lines into one
+// * merge lines into one (done, with --merge)
 // * remove redundant PU/PD commands (different from removing duplicates, although maybe we can do that in the same pass?)
-// * only draw overlapping line segments once
-// * omit/truncate lines that draw outside of the drawable area
+// * only draw overlapping line segments once (done, with --dedupe)
+// * omit/truncate lines that draw outside of the drawable area (done, and with --clip)
 // * remove all of the superfluous spaces in commands :)
 
 // pen plotter behaviour to verify:
 // * how does PD choose to use absolute vs relative coords?
 
-// this code is not ready yet, and currently has many bugs and just overall doesn't work!
-// you probably shouldn't try to use it!
-
-fn main() {
-    let parsed_commands = parse_commands(std::fs::read_to_string("input.hpgl").unwrap()).unwrap();
-    let canonical_commands = canonicalize(parsed_commands);
-    let unopt_shape_map = canonical_commands_to_shapes(canonical_commands);
-    let opt_shape_map = optimize_all_colors(unopt_shape_map);
-    let opt_commands = shapes_to_commands(opt_shape_map);
-    println!(
-        "{}",
-        canonical_commands_to_string(opt_commands));
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "optimize",
+    about = "Reorders the lines in a HPGL file to cut down on how far the pen moves while it's up."
+)]
+struct Args {
+    #[structopt(
+        default_value = "-",
+        help = "HPGL file to optimize, or '-' to read from stdin"
+    )]
+    file: PathBuf,
+    #[structopt(
+        long,
+        help = "Only draw each part of a line once, leaving out parts that an earlier line with \
+            the same pen already drew"
+    )]
+    dedupe: bool,
+    #[structopt(
+        long,
+        help = "With --dedupe, keep lines that go back over themselves (which is usually done on \
+            purpose, to make them darker), and only leave out what other lines already drew"
+    )]
+    keep_retraced: bool,
+    #[structopt(
+        long,
+        help = "Join up lines that end where another one starts, so the pen doesn't have to be \
+            lifted in between, and drop points that are in a straight line"
+    )]
+    merge: bool,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "How far apart the ends of lines can be for --merge to join them, in plotter units"
+    )]
+    merge_tolerance: u32,
+    #[structopt(
+        long,
+        help = "Drop points that don't change the shape of a line by more than this many plotter \
+            units"
+    )]
+    simplify: Option<f64>,
+    #[structopt(
+        long,
+        help = "After ordering, refine the order with 2-opt, for up to this many passes"
+    )]
+    improve: Option<usize>,
+    #[structopt(
+        long,
+        help = "Refine the order with 2-opt for up to this many seconds per pen (the output then \
+            depends on how fast the computer is)"
+    )]
+    improve_time: Option<f64>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["merge", "improve", "improve_time"],
+        help = "Draw each pen's lines in the same order as in the file, for drawings where it \
+            matters which lines go on top (only the order of the pens changes)"
+    )]
+    keep_order: bool,
+    #[structopt(
+        long,
+        help = "Which order to draw the pens in: a list of pen numbers like 3,1,2 (with any other \
+            pens after, by number), or 'lightest-first' or 'darkest-first' to go by the colors in \
+            --palette"
+    )]
+    pen_order: Option<PenOrder>,
+    #[structopt(
+        long,
+        help = "A TOML file giving the color of each pen, for --pen-order"
+    )]
+    palette: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Only draw what's inside of this region, given as the lower left and upper right \
+            corners of a rectangle, or the corners of a polygon, like 0,0,1000,0,500,800"
+    )]
+    clip: Option<ClipRegion>,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
 }
 
-fn optimize_all_colors(color_to_shapes: HashMap<u8, Vec<Shape>>) -> HashMap<u8, Vec<Shape>>{
-    let mut out = HashMap::new();
-    for (color, shapes) in color_to_shapes.iter() {
-        out.insert(*color, order_shapes(shapes.clone()));
-    }
-    out
+fn meters(units: f64) -> String {
+    format!("{:.2} m", units / UNITS_PER_CM / 100.0)
 }
 
-fn shapes_to_commands(color_to_shapes: HashMap<u8, Vec<Shape>>) -> Vec<CanonicalCommand>{
-    let mut out = vec![];
-    for (color, shapes) in color_to_shapes {
-        if color != 0 {
-            out.push(CanonicalCommand::SelectPen(color));
-            for shape in shapes {
-                out.push(CanonicalCommand::PenUp);
-                for (i, point) in shape.iter().enumerate() {
-                    out.push(CanonicalCommand::PlotAbsolute(*point));
-                    if i == 0 {
-                        out.push(CanonicalCommand::PenDown);
-                    }
-                }
-            }
-        }
-    }
-    out.push(CanonicalCommand::PenUp);
-    out.push(CanonicalCommand::SelectPen(0));
-
-    out
+fn stats(cmds: &[Command], model: &Model) -> Stats {
+    hpgl::stats(cmds.iter().cloned(), model).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
 }
 
-type Shape = Vec<Point>;
+fn main() -> std::io::Result<()> {
+    let args = Args::from_args();
+    let mut had_errors = false;
+    let cmds: Vec<Command> = CommandReader::open(&args.file)?
+        .filter_map(|cmd| {
+            cmd.map_err(|e| {
+                eprintln!("{}", e.diagnostic(&args.file));
+                had_errors = true;
+            })
+            .ok()
+        })
+        .collect();
+    if had_errors {
+        std::process::exit(1);
+    }
+    let before = stats(&cmds, &args.model);
+    let palette = args.palette.as_ref().map(|path| {
+        Palette::read(path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        })
+    });
+    let first_pens = match &args.pen_order {
+        Some(order) => order.pens(palette.as_ref()).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }),
+        None if palette.is_some() => {
+            eprintln!("error: --palette is only used to order the pens, with --pen-order");
+            std::process::exit(1);
+        }
+        None => vec![],
+    };
 
-fn canonical_commands_to_shapes(cmds: Vec<CanonicalCommand>) -> HashMap<u8, Vec<Shape>> {
-    let mut active_color = 0;
-    let mut current_shape = vec![];
-    let mut is_pen_down = false;
-    let mut color_to_shapes = HashMap::new();
-    for cmd in cmds{
-        match cmd {
-            CanonicalCommand::SelectPen(color) => {
-                active_color = color;
-                // selecting pen ends current shape
-                if !color_to_shapes.contains_key(&active_color){
-                    color_to_shapes.insert(active_color, vec![]);
-                }
-                if current_shape.len() > 0 {
-                    color_to_shapes.get_mut(&active_color).unwrap().push(current_shape);
-                    current_shape = vec![];
-                }
+    // labels and dashed lines are always drawn as strokes, since otherwise they'd lose track of
+    // where they go once the lines are reordered, and anything the pen can't reach is left out,
+    // since it wouldn't be drawn anyway
+    let options = CanonicalizeOptions {
+        expand_line_types: true,
+        expand_labels: true,
+        hard_clip_limits: Some(args.model.hard_clip_limits),
+        clip_region: args.clip.clone(),
+    };
+    let mut drawing = Drawing::from_commands(cmds, &options).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    // lines drawn with no pen selected don't show up on the page
+    drawing.layers.remove(&0);
+    let budget = if args.improve.is_some() || args.improve_time.is_some() {
+        Some(Budget {
+            passes: args.improve,
+            time: args.improve_time.map(Duration::from_secs_f64),
+        })
+    } else {
+        None
+    };
+    let mut deduped = 0.0;
+    let mut lifts_removed = 0;
+    let mut points_removed = 0;
+    let mut saved = 0.0;
+    // each pen starts where the last one left off
+    let mut position = Point { x: 0, y: 0 };
+    let pens = drawing.pens(&first_pens);
+    for pen in &pens {
+        if let (Some(palette), Some(PenOrder::LightestFirst | PenOrder::DarkestFirst)) =
+            (&palette, &args.pen_order)
+        {
+            if drawing.layers.contains_key(pen) && !palette.pens.contains_key(pen) {
+                eprintln!(
+                    "warning: pen {} isn't in the palette, so it's drawn after the ones that are",
+                    pen
+                );
             }
-            CanonicalCommand::PlotAbsolute(point) => current_shape.push(point),
-            CanonicalCommand::PenDown => is_pen_down = true,
-            CanonicalCommand::PenUp => {
-                is_pen_down = false;
-                if !color_to_shapes.contains_key(&active_color){
-                    color_to_shapes.insert(active_color, vec![]);
-                }
-                if current_shape.len() > 0 {
-                    color_to_shapes.get_mut(&active_color).unwrap().push(current_shape);
-                    current_shape = vec![];
+        }
+        // lines aren't moved past the instructions in between them, like a `VS`
+        drawing.map_runs(*pen, |mut polylines| {
+            if args.dedupe {
+                let (remaining, removed) = dedupe(polylines, args.keep_retraced);
+                polylines = remaining;
+                deduped += removed;
+            }
+            if args.merge {
+                let before = polylines.len();
+                polylines = merge(polylines, args.merge_tolerance);
+                lifts_removed += before - polylines.len();
+            }
+            if let Some(tolerance) = args.simplify {
+                for polyline in polylines.iter_mut() {
+                    let before = polyline.len();
+                    *polyline = simplify(polyline, tolerance);
+                    points_removed += before - polyline.len();
                 }
             }
-            CanonicalCommand::Initialize => panic!("What is this even for?"),
-        }
-    }
-    color_to_shapes
-}
-
-/// Euclidean
-fn distance(p1: &Point, p2: &Point) -> f64 {
-    f64::sqrt(((p1.x - p2.x).pow(2) + (p1.y - p2.y).pow(2)) as f64)
-}
-
-/// Given a Point finds the Shape whose starting point is nearest the given point.
-fn find_closest(p: &Point, shapes: &Vec<Shape>) -> Option<usize> {
-    let mut best_shape = None;
-    let mut best_distance: f64 = f64::INFINITY;
-    for (i, shape) in shapes.iter().enumerate() {
-        let point = shape[0];
-        let dist = distance(&point, &p);
-        if dist < best_distance {
-            best_distance = dist;
-            best_shape = Some(i);
-        }
+            if !args.keep_order {
+                polylines = order_shapes(polylines, position);
+            }
+            if let Some(budget) = budget {
+                saved += improve(&mut polylines, position, budget);
+            }
+            if let Some(last) = polylines.last().and_then(|p| p.last()) {
+                position = *last;
+            }
+            polylines
+        });
     }
+    let out = drawing.to_commands_with_pen_order(&first_pens);
+    let after = stats(&out, &args.model);
 
-    best_shape
-}
-
-/// Takes all shapes to draw with one pen color and figures out a better order to draw them in
-/// Starts with first shape
-/// After finishing drawing a shape finds nearest undrawn shape to draw next
-/// Possible improvements:
-///     * use Vec<Option<Shape>> instead of .remove
-///     * use some kind of tree datastructure
-///     * change order in which an individual shape is drawn (start at a different point)
-///     * write unit tests
-fn order_shapes(mut shapes: Vec<Shape>) -> Vec<Shape> {
-    if shapes.len() == 0 {
-        return vec![];
+    let stdout = std::io::stdout();
+    let mut writer = CommandWriter::new(stdout.lock(), Style::OnePerLine);
+    writer.write_commands(&out)?;
+    writer.into_inner().flush()?;
+    eprintln!(
+        "pen up travel: {} before, {} after",
+        meters(before.travel_distance),
+        meters(after.travel_distance)
+    );
+    eprintln!(
+        "pen changes: {} before, {} after",
+        before.pen_changes, after.pen_changes
+    );
+    if args.dedupe {
+        eprintln!("removing overlaps saved {} of drawing", meters(deduped));
     }
-    let mut prev_shape = shapes.remove(0);
-    let mut out = vec![prev_shape.clone()];
-
-    while shapes.len() > 0 {
-        let next_shape = find_closest(prev_shape.last().unwrap(), &shapes).unwrap();
-        prev_shape = shapes.remove(next_shape);
-        out.push(prev_shape.clone());
+    if args.merge {
+        eprintln!("merging removed {} pen lifts", lifts_removed);
     }
-
-    out
-}
-
-
-
-fn remove_duplicate_pen_commands(cmds: Vec<Command>) -> Vec<Command> {
-    let mut out = vec![];
-    let mut previous: Option<Command> = None;
-    for cmd in cmds.iter() {
-        match (previous, cmd) {
-            (None, cmd) => previous = Some(cmd.clone()),
-            (Some(Command::PenUp(_)) | Some(Command::PenDown(_)), Command::PenUp(pu)) => {
-                previous = Some(Command::PenUp(pu.to_vec()))
-            }
-            (Some(Command::PenUp(_)) | Some(Command::PenDown(_)), Command::PenDown(pd)) => {
-                previous = Some(Command::PenDown(pd.to_vec()))
-            }
-            (Some(prev), new) => {
-                out.push(prev);
-                previous = Some(new.clone());
-            }
-        }
+    if args.simplify.is_some() {
+        eprintln!("simplifying removed {} points", points_removed);
     }
-    if let Some(prev) = previous {
-        out.push(prev);
+    if budget.is_some() {
+        eprintln!("2-opt saved {} of that", meters(saved));
     }
-    out
-}
-
-#[test]
-fn test_duplicates() {
-    let test_commands = parse_commands(String::from("IN;PU;PD;PA1000,1000;PU;PU;")).unwrap();
-    let expected_commands = parse_commands(String::from("IN;PD;PA1000,1000;PU;")).unwrap();
-    let deduped = remove_duplicate_pen_commands(test_commands);
-    assert_eq!(deduped, expected_commands);
+    Ok(())
 }
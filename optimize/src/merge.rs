@@ -0,0 +1,177 @@
+use crate::grid::{distance_squared, Grid};
+use hpgl::{Point, Polyline};
+
+/// Which end of a shape a point in the grid is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum End {
+    First,
+    Last,
+}
+
+fn remove_ends(grid: &mut Grid<(usize, End)>, i: usize, shape: &[Point]) {
+    grid.remove(shape[0], (i, End::First));
+    grid.remove(shape[shape.len() - 1], (i, End::Last));
+}
+
+/// Joins up shapes that end where another one starts (or ends, by drawing it backwards), so that
+/// the pen doesn't have to be lifted in between, and then drops points that are in a straight
+/// line with the ones on either side of them (see `remove_collinear`).
+///
+/// Ends that are up to `tolerance` plotter units apart are joined with a short line, and the
+/// closest end is always picked first. The shapes come out in the order their first piece was in.
+pub fn merge(shapes: Vec<Polyline>, tolerance: u32) -> Vec<Polyline> {
+    let mut shapes: Vec<Option<Polyline>> = shapes
+        .into_iter()
+        .filter(|shape| !shape.is_empty())
+        .map(Some)
+        .collect();
+    let ends = shapes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, shape)| {
+            let shape = shape.as_ref().unwrap();
+            vec![
+                (shape[0], (i, End::First)),
+                (shape[shape.len() - 1], (i, End::Last)),
+            ]
+        })
+        .collect();
+    let mut grid = Grid::new(ends);
+    let tolerance = i64::from(tolerance) * i64::from(tolerance);
+
+    let mut out = vec![];
+    for i in 0..shapes.len() {
+        let mut path = match shapes[i].take() {
+            Some(path) => path,
+            None => continue,
+        };
+        remove_ends(&mut grid, i, &path);
+        // add on to the end, and then turn it around and do the same to the start
+        for _ in 0..2 {
+            loop {
+                let end = path[path.len() - 1];
+                let (j, other_end) = match grid.nearest(end) {
+                    Some((point, next)) if distance_squared(point, end) <= tolerance => next,
+                    _ => break,
+                };
+                let mut other = shapes[j].take().unwrap();
+                remove_ends(&mut grid, j, &other);
+                if other_end == End::Last {
+                    other.reverse();
+                }
+                if other[0] == end {
+                    path.extend_from_slice(&other[1..]);
+                } else {
+                    path.extend(other);
+                }
+            }
+            path.reverse();
+        }
+        out.push(remove_collinear(path));
+    }
+    out
+}
+
+/// Drops points that are the same as the one before them, or that are in a straight line
+/// between the ones on either side of them, since the line is drawn just the same without them.
+/// Points where the line turns back on itself are kept.
+pub fn remove_collinear(shape: Polyline) -> Polyline {
+    let mut out: Polyline = Vec::with_capacity(shape.len());
+    for point in shape {
+        match out[..] {
+            [.., last] if last == point => {}
+            [.., a, b] if is_straight(a, b, point) => {
+                *out.last_mut().unwrap() = point;
+            }
+            _ => out.push(point),
+        }
+    }
+    out
+}
+
+/// Whether going from `a` to `b` to `c` carries on in the same direction.
+fn is_straight(a: Point, b: Point, c: Point) -> bool {
+    let (abx, aby) = (
+        i64::from(b.x) - i64::from(a.x),
+        i64::from(b.y) - i64::from(a.y),
+    );
+    let (bcx, bcy) = (
+        i64::from(c.x) - i64::from(b.x),
+        i64::from(c.y) - i64::from(b.y),
+    );
+    abx * bcy == aby * bcx && abx * bcx + aby * bcy > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_remove_collinear() {
+        assert_eq!(
+            remove_collinear(vec![
+                p(0, 0),
+                p(10, 0),
+                p(10, 0),
+                p(20, 0),
+                p(20, 10),
+                p(20, 20),
+                p(20, 5),
+                p(30, 20),
+            ]),
+            vec![p(0, 0), p(20, 0), p(20, 20), p(20, 5), p(30, 20)]
+        );
+        assert_eq!(remove_collinear(vec![p(5, 5), p(5, 5)]), vec![p(5, 5)]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let shapes = vec![
+            vec![p(100, 0), p(200, 0)],
+            // far away from everything else
+            vec![p(5000, 5000), p(6000, 5000)],
+            vec![p(200, 100), p(200, 0)],
+            vec![p(0, 0), p(100, 0)],
+            // close enough to the end of the last one
+            vec![p(203, 104), p(300, 200)],
+        ];
+        assert_eq!(
+            merge(shapes.clone(), 0),
+            vec![
+                vec![p(0, 0), p(200, 0), p(200, 100)],
+                vec![p(5000, 5000), p(6000, 5000)],
+                vec![p(203, 104), p(300, 200)],
+            ]
+        );
+        assert_eq!(
+            merge(shapes, 5),
+            vec![
+                vec![p(0, 0), p(200, 0), p(200, 100), p(203, 104), p(300, 200)],
+                vec![p(5000, 5000), p(6000, 5000)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_loop() {
+        // the sides of a square, in no particular order
+        let shapes = vec![
+            vec![p(0, 0), p(100, 0)],
+            vec![p(100, 100), p(0, 100)],
+            vec![p(100, 100), p(100, 0)],
+            vec![p(0, 0), p(0, 100)],
+            vec![p(20, 20)],
+        ];
+        assert_eq!(
+            merge(shapes, 0),
+            vec![
+                vec![p(0, 0), p(100, 0), p(100, 100), p(0, 100), p(0, 0)],
+                vec![p(20, 20)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use crate::grid::Grid;
+use hpgl::{Point, Polyline};
+
+/// Euclidean
+pub fn distance(p1: &Point, p2: &Point) -> f64 {
+    let dx = f64::from(p1.x) - f64::from(p2.x);
+    let dy = f64::from(p1.y) - f64::from(p2.y);
+    dx.hypot(dy)
+}
+
+/// How far the pen moves while it's up to draw the shapes in order, starting from `start`.
+pub fn travel(start: Point, shapes: &[Polyline]) -> f64 {
+    let mut position = start;
+    let mut total = 0.0;
+    for shape in shapes {
+        if let (Some(first), Some(last)) = (shape.first(), shape.last()) {
+            total += distance(&position, first);
+            position = *last;
+        }
+    }
+    total
+}
+
+/// Whether a shape ends where it starts, so it can be started from any of its points.
+fn is_closed(shape: &[Point]) -> bool {
+    shape.len() > 2 && shape.first() == shape.last()
+}
+
+/// Which point a shape is started from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Entry {
+    /// Drawn as it is.
+    Forward,
+    /// An open shape drawn from its last point back to its first.
+    Reversed,
+    /// A closed shape started from the point with this index instead.
+    Rotated(usize),
+}
+
+impl Entry {
+    fn start(self, shape: &[Point]) -> Point {
+        match self {
+            Entry::Forward => shape[0],
+            Entry::Reversed => shape[shape.len() - 1],
+            Entry::Rotated(i) => shape[i],
+        }
+    }
+
+    fn apply(self, mut shape: Polyline) -> Polyline {
+        match self {
+            Entry::Forward => shape,
+            Entry::Reversed => {
+                shape.reverse();
+                shape
+            }
+            Entry::Rotated(i) => {
+                // the last point is the same as the first, so it's dropped and the new start
+                // point is added on the end instead
+                shape.pop();
+                shape.rotate_left(i);
+                shape.push(shape[0]);
+                shape
+            }
+        }
+    }
+}
+
+/// Every point a shape can be started from.
+fn entries(shape: &[Point]) -> Vec<Entry> {
+    if is_closed(shape) {
+        let mut entries = vec![Entry::Forward];
+        entries.extend((1..shape.len() - 1).map(Entry::Rotated));
+        entries
+    } else if shape.len() > 1 {
+        vec![Entry::Forward, Entry::Reversed]
+    } else {
+        vec![Entry::Forward]
+    }
+}
+
+/// Takes all shapes to draw with one pen color and figures out a better order to draw them in
+/// Starts with the pen at `start`
+/// After finishing drawing a shape finds nearest undrawn shape to draw next, picking the first
+/// one if there's a tie, so the order is always the same for the same input
+/// Open shapes can be drawn backwards, and closed ones can be started from any of their points,
+/// if that starts them closer
+/// Every point a shape can be started from goes in a `Grid`, so finding the nearest one doesn't
+/// mean looking at all of them, and this takes roughly n log n time rather than n^2
+pub fn order_shapes(shapes: Vec<Polyline>, start: Point) -> Vec<Polyline> {
+    let mut shapes: Vec<Option<Polyline>> = shapes
+        .into_iter()
+        .filter(|shape| !shape.is_empty())
+        .map(Some)
+        .collect();
+    let starts = shapes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, shape)| {
+            let shape = shape.as_ref().unwrap();
+            entries(shape)
+                .into_iter()
+                .map(move |entry| (entry.start(shape), (i, entry)))
+        })
+        .collect();
+    let mut grid = Grid::new(starts);
+    let mut out = vec![];
+    let mut position = start;
+
+    while let Some((_, (next_shape, entry))) = grid.nearest(position) {
+        let shape = shapes[next_shape].take().unwrap();
+        for other in entries(&shape) {
+            grid.remove(other.start(&shape), (next_shape, other));
+        }
+        let shape = entry.apply(shape);
+        position = *shape.last().unwrap();
+        out.push(shape);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::grid::distance_squared;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    /// How shapes were ordered before they could be reversed or rotated, only looking at where
+    /// each one starts.
+    fn order_by_start(mut shapes: Vec<Polyline>) -> Vec<Polyline> {
+        let mut out = vec![shapes.remove(0)];
+        while !shapes.is_empty() {
+            let end = *out.last().unwrap().last().unwrap();
+            let next = (0..shapes.len())
+                .min_by(|a, b| {
+                    let a = distance(&shapes[*a][0], &end);
+                    let b = distance(&shapes[*b][0], &end);
+                    a.partial_cmp(&b).unwrap()
+                })
+                .unwrap();
+            out.push(shapes.remove(next));
+        }
+        out
+    }
+
+    /// The same as `order_shapes`, but looking at every shape to find the nearest one.
+    fn order_shapes_slowly(mut shapes: Vec<Polyline>, start: Point) -> Vec<Polyline> {
+        let mut out = vec![];
+        let mut position = start;
+        while !shapes.is_empty() {
+            let (next, entry) = (0..shapes.len())
+                .flat_map(|i| entries(&shapes[i]).into_iter().map(move |e| (i, e)))
+                .min_by_key(|(i, e)| (distance_squared(e.start(&shapes[*i]), position), *i, *e))
+                .unwrap();
+            let shape = entry.apply(shapes.remove(next));
+            position = *shape.last().unwrap();
+            out.push(shape);
+        }
+        out
+    }
+
+    #[test]
+    fn test_same_as_linear_search() {
+        let shapes: Vec<Polyline> = (0..300)
+            .map(|i| {
+                let a = p((i * 7919) % 3001, (i * 104_729) % 2003);
+                let b = p(a.x + (i * 31) % 200, a.y - (i * 17) % 150);
+                match i % 3 {
+                    0 => vec![a],
+                    1 => vec![a, b],
+                    _ => vec![a, b, p(b.x, a.y), a],
+                }
+            })
+            .collect();
+        let start = p(1500, -400);
+        assert_eq!(
+            order_shapes(shapes.clone(), start),
+            order_shapes_slowly(shapes, start)
+        );
+    }
+
+    #[test]
+    fn test_entries() {
+        let open = vec![p(0, 0), p(10, 0), p(10, 10)];
+        assert_eq!(entries(&open), vec![Entry::Forward, Entry::Reversed]);
+        assert_eq!(
+            Entry::Reversed.apply(open),
+            vec![p(10, 10), p(10, 0), p(0, 0)]
+        );
+
+        let closed = vec![p(0, 0), p(10, 0), p(10, 10), p(0, 0)];
+        assert_eq!(
+            entries(&closed),
+            vec![Entry::Forward, Entry::Rotated(1), Entry::Rotated(2)]
+        );
+        assert_eq!(
+            Entry::Rotated(2).apply(closed),
+            vec![p(10, 10), p(0, 0), p(10, 0), p(10, 10)]
+        );
+
+        assert_eq!(entries(&[p(5, 5)]), vec![Entry::Forward]);
+    }
+
+    #[test]
+    fn test_order_shapes() {
+        let shapes = vec![
+            vec![p(0, 0), p(100, 0)],
+            vec![p(5000, 0), p(6000, 0)],
+            vec![p(200, 0), p(300, 0)],
+            vec![p(400, 0)],
+            // as close to (300, 0) as the dot at (400, 0), so it goes after it
+            vec![p(0, 0), p(200, 0)],
+        ];
+        assert_eq!(
+            order_shapes(shapes, p(0, 0)),
+            vec![
+                vec![p(0, 0), p(100, 0)],
+                vec![p(200, 0), p(300, 0)],
+                vec![p(400, 0)],
+                vec![p(200, 0), p(0, 0)],
+                vec![p(5000, 0), p(6000, 0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hatching() {
+        // a hatch fill, with every line drawn left to right
+        let shapes: Vec<Polyline> = (0..50)
+            .map(|i| vec![p(0, i * 20), p(4000, i * 20)])
+            .collect();
+        let start = p(0, 0);
+        let greedy = travel(start, &order_by_start(shapes.clone()));
+        let ordered = order_shapes(shapes, start);
+        // every other line is drawn backwards, so the pen only moves between lines
+        assert_eq!(travel(start, &ordered), 49.0 * 20.0);
+        assert!(travel(start, &ordered) < greedy / 2.0);
+        assert_eq!(ordered[1], vec![p(4000, 20), p(0, 20)]);
+    }
+
+    #[test]
+    fn test_loops() {
+        // a row of squares, starting from alternate corners
+        let squares: Vec<Polyline> = (0..20)
+            .map(|i| {
+                let x = i * 1000;
+                let square = vec![p(x, 0), p(x, 500), p(x + 500, 500), p(x + 500, 0), p(x, 0)];
+                if i % 2 == 0 {
+                    square
+                } else {
+                    Entry::Rotated(2).apply(square)
+                }
+            })
+            .collect();
+        let start = p(0, 0);
+        let greedy = travel(start, &order_by_start(squares.clone()));
+        let ordered = order_shapes(squares, start);
+        // every square is started from its bottom left corner
+        assert_eq!(travel(start, &ordered), 19.0 * 1000.0);
+        assert!(travel(start, &ordered) < greedy);
+        assert_eq!(
+            ordered[1],
+            vec![
+                p(1000, 0),
+                p(1000, 500),
+                p(1500, 500),
+                p(1500, 0),
+                p(1000, 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Which order the pens are drawn in, given as a list of pens, or worked out from how light the
+//! colors in a palette file are.
+//!
+//! A palette file is TOML, with a table for each pen, named by its number:
+//!
+//! ```toml
+//! [1]
+//! color = "#ffd700"
+//! # this is optional, and is only used in messages
+//! name = "yellow"
+//!
+//! [2]
+//! color = "#1a1a1a"
+//! name = "black"
+//! ```
+
+use hpgl::Pen;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What a pen draws like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteEntry {
+    pub name: String,
+    /// The red, green and blue parts of its color, in sRGB.
+    pub color: [u8; 3],
+}
+
+impl PaletteEntry {
+    /// How light the pen's color looks, from 0 for black to 1 for white (which is its relative
+    /// luminance).
+    pub fn lightness(&self) -> f64 {
+        let linear = |c: u8| {
+            let c = f64::from(c) / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let [r, g, b] = self.color;
+        0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+    }
+}
+
+/// The pens in a palette file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub pens: BTreeMap<Pen, PaletteEntry>,
+}
+
+/// A pen as it's written in the palette file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PenDefinition {
+    color: String,
+    #[serde(default)]
+    name: String,
+}
+
+impl Palette {
+    pub fn read(path: &Path) -> Result<Palette, PaletteError> {
+        let text = std::fs::read_to_string(path).map_err(|error| PaletteError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Palette::parse(&text, path)
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Palette, PaletteError> {
+        let definitions: BTreeMap<String, PenDefinition> =
+            toml::from_str(text).map_err(|error| PaletteError::Parse {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        let mut pens = BTreeMap::new();
+        for (pen, d) in definitions {
+            let invalid = |reason: &str| PaletteError::Invalid {
+                path: path.to_path_buf(),
+                pen: pen.clone(),
+                reason: reason.to_string(),
+            };
+            let number = match pen.parse::<Pen>() {
+                Ok(number) if number != 0 => number,
+                _ => return Err(invalid("pens are numbered from 1 to 255")),
+            };
+            let color = parse_color(&d.color)
+                .ok_or_else(|| invalid("colors have to be written like \"#ffd700\""))?;
+            pens.insert(
+                number,
+                PaletteEntry {
+                    name: d.name,
+                    color,
+                },
+            );
+        }
+        Ok(Palette { pens })
+    }
+}
+
+/// Reads a color written as `#rrggbb`.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([part(0)?, part(2)?, part(4)?])
+}
+
+/// Why a palette couldn't be read.
+#[derive(Debug)]
+pub enum PaletteError {
+    Read {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        pen: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Read { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            }
+            PaletteError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            PaletteError::Invalid { path, pen, reason } => {
+                write!(f, "{}: pen '{}': {}", path.display(), pen, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Which order to draw the pens in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PenOrder {
+    /// These pens first, in this order.
+    Pens(Vec<Pen>),
+    /// The pens in the palette, from the lightest color to the darkest, so that dark lines end
+    /// up on top of light ones.
+    LightestFirst,
+    /// The pens in the palette, from the darkest color to the lightest.
+    DarkestFirst,
+}
+
+impl PenOrder {
+    /// The pens to draw first, in order. Any other pens are drawn after these (see
+    /// `Drawing::pens`). Going by lightness needs a palette, and pens with the same lightness
+    /// go in order by number.
+    pub fn pens(&self, palette: Option<&Palette>) -> Result<Vec<Pen>, String> {
+        let palette = match (self, palette) {
+            (PenOrder::Pens(pens), _) => return Ok(pens.clone()),
+            (_, Some(palette)) => palette,
+            (_, None) => return Err("ordering pens by lightness needs a palette".to_string()),
+        };
+        let mut pens: Vec<(Pen, f64)> = palette
+            .pens
+            .iter()
+            .map(|(pen, entry)| (*pen, entry.lightness()))
+            .collect();
+        // the pens are already in order by number, and sorting is stable
+        pens.sort_by(|(_, a), (_, b)| match self {
+            PenOrder::DarkestFirst => a.partial_cmp(b).unwrap(),
+            _ => b.partial_cmp(a).unwrap(),
+        });
+        Ok(pens.into_iter().map(|(pen, _)| pen).collect())
+    }
+}
+
+/// Reads a pen order: `lightest-first`, `darkest-first`, or a comma separated list of pen
+/// numbers.
+impl FromStr for PenOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lightest-first" => return Ok(PenOrder::LightestFirst),
+            "darkest-first" => return Ok(PenOrder::DarkestFirst),
+            _ => {}
+        }
+        let mut pens = vec![];
+        for pen in s.split(',').map(str::trim) {
+            match pen.parse::<Pen>() {
+                Ok(0) | Err(_) => {
+                    return Err(format!(
+                        "'{}' isn't a pen number from 1 to 255, 'lightest-first' or \
+                         'darkest-first'",
+                        pen
+                    ))
+                }
+                Ok(number) if pens.contains(&number) => {
+                    return Err(format!("pen {} is listed more than once", number))
+                }
+                Ok(number) => pens.push(number),
+            }
+        }
+        Ok(PenOrder::Pens(pens))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn palette(text: &str) -> Result<Palette, PaletteError> {
+        Palette::parse(text, Path::new("palette.toml"))
+    }
+
+    #[test]
+    fn test_palette() {
+        let p = palette(
+            r##"
+            [1]
+            color = "#1A1A1A"
+            name = "black"
+            [2]
+            color = "#ffd700"
+            [3]
+            color = "#ffffff"
+            [4]
+            color = "#0000ff"
+            [5]
+            color = "#ffffff"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(p.pens[&1].color, [0x1a, 0x1a, 0x1a]);
+        assert_eq!(p.pens[&1].name, "black");
+        assert_eq!(p.pens[&2].name, "");
+        assert_eq!(
+            PenOrder::LightestFirst.pens(Some(&p)),
+            Ok(vec![3, 5, 2, 4, 1])
+        );
+        assert_eq!(
+            PenOrder::DarkestFirst.pens(Some(&p)),
+            Ok(vec![1, 4, 2, 3, 5])
+        );
+        assert!(PenOrder::LightestFirst.pens(None).is_err());
+        assert_eq!(PenOrder::Pens(vec![2, 1]).pens(None), Ok(vec![2, 1]));
+
+        let error = palette("[0]\ncolor = \"#000000\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "palette.toml: pen '0': pens are numbered from 1 to 255"
+        );
+        assert!(palette("[1]\ncolor = \"black\"").is_err());
+        assert!(palette("[1]\ncolor = \"#00000\"").is_err());
+        assert!(palette("[1]\ncolour = \"#000000\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_pen_order() {
+        assert_eq!("3, 1,2".parse(), Ok(PenOrder::Pens(vec![3, 1, 2])));
+        assert_eq!("lightest-first".parse(), Ok(PenOrder::LightestFirst));
+        assert_eq!("darkest-first".parse(), Ok(PenOrder::DarkestFirst));
+        assert!("1,0".parse::<PenOrder>().is_err());
+        assert!("1,2,1".parse::<PenOrder>().is_err());
+        assert!("lightest".parse::<PenOrder>().is_err());
+    }
+}
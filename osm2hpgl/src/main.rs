@@ -3,6 +3,22 @@
 use std::fs;
 use std::collections::HashMap;
 
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::Point;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
+    #[structopt(
+        long,
+        help = "Leave out points that don't change the shape of a way by more than this many \
+            plotter units"
+    )]
+    simplify: Option<f64>,
+}
+
 fn get_tags(e: &minidom::Element) -> HashMap<String, String> {
     let mut out = HashMap::new();
     for node in e.children() {
@@ -140,19 +156,24 @@ fn in_bounds(p: &Node, b: &Bounds) -> bool {
     p.lat > b.minlat && p.lat < b.maxlat && p.lon > b.minlon && p.lon < b.maxlon
 }
 
-fn transform(p: &Node, b: &Bounds) -> (i64, i64) {
-    let scalex = 10300.0 / (b.maxlat - b.minlat);
-    let scaley = 7650.0 / (b.maxlon - b.minlon);
+/// Where a node goes on the page. Nodes far enough outside of the map that they'd be out of the
+/// range of plotter coordinates are clamped to it.
+fn transform(p: &Node, b: &Bounds, model: &Model) -> Point {
+    let (min, max) = model.hard_clip_limits;
+    let (width, height) = model.size();
+    let scalex = f64::from(width) / (b.maxlat - b.minlat);
+    let scaley = f64::from(height) / (b.maxlon - b.minlon);
+    let clamp = |v: f64| v.clamp(f64::from(i32::MIN), f64::from(i32::MAX)) as i32;
 
-    (
-        10300 - ((p.lat - b.minlat) * scalex) as i64,
-        ((p.lon - b.minlon) * scaley) as i64,
-    )
+    Point {
+        x: clamp(f64::from(max.x) - ((p.lat - b.minlat) * scalex).trunc()),
+        y: clamp(f64::from(min.y) + ((p.lon - b.minlon) * scaley).trunc()),
+    }
 }
 
-fn print_way(way: u64, map: &Map) {
+fn print_way(way: u64, map: &Map, model: &Model, simplify: Option<f64>) {
     if let Some(Element::Way(way)) = map.elements.get(&way) {
-        let mut first = true;
+        let mut points = vec![];
         for (i, node_id) in way.nodes.iter().enumerate() {
             if let Some(Element::Node(node)) = map.elements.get(&node_id) {
                 // this code is really gross :(
@@ -169,19 +190,24 @@ fn print_way(way: u64, map: &Map) {
                 }
 
                 if print_point {
-                    let (x, y) = transform(node, &map.bounds);
-                    if first {
-                        println!("PU;");
-                        first = false;
-                    }
-                    println!("PA {},{};PD;", x, y);
+                    points.push(transform(node, &map.bounds, model));
                 }
             }
         }
+        if let Some(tolerance) = simplify {
+            points = hpgl::simplify(&points, tolerance);
+        }
+        if !points.is_empty() {
+            println!("PU;");
+        }
+        for point in points {
+            println!("PA {},{};PD;", point.x, point.y);
+        }
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::from_args();
     let map_str = fs::read_to_string("map.osm")?;
     let root: minidom::Element = map_str.parse().unwrap();
 
@@ -212,7 +238,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else {
             println!("SP0;");
         }
-        print_way(*id, &map);
+        print_way(*id, &map, &args.model, args.simplify);
     }
 
     println!("SP0;");
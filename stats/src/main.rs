@@ -0,0 +1,80 @@
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CommandReader, StatsCollector, UNITS_PER_CM};
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(help = "HPGL file to measure, or '-' to read from stdin")]
+    file: PathBuf,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
+}
+
+fn meters(units: f64) -> String {
+    format!("{:.2} m", units / UNITS_PER_CM / 100.0)
+}
+
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}h {:02}m {:02}s",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::from_args();
+    let cmds = CommandReader::open(&args.file)?;
+    let mut had_errors = false;
+    let mut collector = StatsCollector::new(args.model);
+
+    for cmd in cmds {
+        let cmd = match cmd {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e.diagnostic(&args.file));
+                had_errors = true;
+                continue;
+            }
+        };
+        if let Err(e) = collector.execute(cmd) {
+            eprintln!("error: {}", e);
+            had_errors = true;
+            break;
+        }
+    }
+
+    let stats = collector.stats();
+    for (pen, distance) in &stats.pen_down_distance {
+        match pen {
+            0 => println!("no pen:       {} (not drawn)", meters(*distance)),
+            pen => println!("pen {}:        {}", pen, meters(*distance)),
+        }
+    }
+    println!("total drawn:  {}", meters(stats.total_pen_down_distance()));
+    println!("travel:       {}", meters(stats.travel_distance));
+    println!("pen lifts:    {}", stats.pen_lifts);
+    println!("pen changes:  {}", stats.pen_changes);
+    match stats.bounds {
+        Some((min, max)) => println!(
+            "bounds:       ({}, {}) to ({}, {}), {:.1} x {:.1} cm",
+            min.x,
+            min.y,
+            max.x,
+            max.y,
+            f64::from(max.x - min.x) / UNITS_PER_CM,
+            f64::from(max.y - min.y) / UNITS_PER_CM
+        ),
+        None => println!("bounds:       nothing is drawn"),
+    }
+    println!("time:         {} (estimated)", duration(stats.time));
+
+    if had_errors {
+        std::process::exit(1);
+    }
+    Ok(())
+}
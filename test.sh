@@ -5,11 +5,14 @@ declare -A projects
 projects[chunker]="stable nightly"
 projects[hpgl]="stable nightly"
 projects[hpgl2gcode]="stable nightly"
-projects[optimize]="nightly"
+projects[optimize]="stable nightly"
 projects[typewriter]="stable nightly"
 projects[viz]="stable nightly"
 projects[osm2hpgl]="nightly"
 projects[canonicalize]="stable nightly"
+projects[lint]="stable nightly"
+projects[stats]="stable nightly"
+projects[hpgl-transform]="stable nightly"
 
 for project in "${!projects[@]}"
 do
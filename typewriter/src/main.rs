@@ -1,3 +1,5 @@
+use hpgl::device::{Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{simplify, ClipRegion, Point};
 use rusttype::{self, Contour, FontCollection, PositionedGlyph, Scale, Segment};
 use std::fs::File;
 use std::io::Read;
@@ -9,16 +11,10 @@ fn error(msg: &str) -> ! {
     std::process::exit(1);
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-struct Point {
-    x: i64,
-    y: i64,
-}
-
 fn convert_point(point: rusttype::Point<f32>) -> Point {
     Point {
-        x: point.x as i64,
-        y: point.y as i64,
+        x: point.x as i32,
+        y: point.y as i32,
     }
 }
 
@@ -39,8 +35,8 @@ fn segment_to_points(segment: Segment) -> Vec<Point> {
                     + 2.0 * (1.0 - t) * t * curve.p[1].y
                     + t * t * curve.p[2].y;
                 let point = Point {
-                    x: x as i64,
-                    y: y as i64,
+                    x: x as i32,
+                    y: y as i32,
                 };
                 res.push(point);
             }
@@ -56,9 +52,6 @@ fn countour_to_points(contour: Contour) -> Vec<Point> {
     }
     let mut dedup_res = vec![];
     for item in res {
-        if item.x < 0 || item.x > 10300 || item.y < 0 || item.y > 7650 {
-            eprintln!("WARNING: point {:?} is out of bounds", &item);
-        }
         if Some(&item) != dedup_res.last() {
             dedup_res.push(item);
         }
@@ -80,7 +73,15 @@ struct Args {
         default_value = "1",
         help = "Makes plotting faster, but less accurate. Higher numbers are faster"
     )]
-    rescale: i64,
+    rescale: i32,
+    #[structopt(
+        long,
+        help = "Leave out points that don't change the shape of a letter by more than this many \
+            plotter units, so the file is smaller and the pen moves more smoothly"
+    )]
+    simplify: Option<f64>,
+    #[structopt(long, default_value = DEFAULT_MODEL, help = MODEL_HELP)]
+    model: Model,
 }
 
 fn main() {
@@ -116,6 +117,9 @@ fn main() {
             },
         )
         .collect();
+    let (lower_left, upper_right) = args.model.hard_clip_limits;
+    let page = ClipRegion::Rectangle(lower_left, upper_right);
+    let mut clipped = false;
     for glyph in glyphs {
         let contours = match glyph.shape() {
             None => continue,
@@ -123,7 +127,7 @@ fn main() {
         };
         for contour in contours {
             let mut points = countour_to_points(contour);
-            if points.len() == 0 {
+            if points.is_empty() {
                 continue;
             }
             if args.rescale != 1 {
@@ -137,13 +141,27 @@ fn main() {
                     .collect();
                 points.dedup();
             }
-            println!("PU;");
-            println!("PA {}, {};", points[0].x, points[0].y);
-            println!("PD;");
-            for point in points {
-                println!("PA {}, {};", point.x, point.y);
+            if let Some(tolerance) = args.simplify {
+                points = simplify(&points, tolerance);
+            }
+            // the parts of letters that are off the page are left out, with the pen lifted over
+            // them
+            let pieces = page.clip_polyline(&points);
+            if pieces.len() != 1 || pieces[0] != points {
+                clipped = true;
+            }
+            for piece in pieces {
+                println!("PU;");
+                println!("PA {}, {};", piece[0].x, piece[0].y);
+                println!("PD;");
+                for point in piece {
+                    println!("PA {}, {};", point.x, point.y);
+                }
+                println!("PU;");
             }
-            println!("PU;");
         }
     }
+    if clipped {
+        eprintln!("WARNING: some of the text is off the page, and was left out");
+    }
 }
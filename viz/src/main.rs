@@ -12,53 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use hpgl::{parse_commands, Command, Point};
-use std::fs;
+use hpgl::device::{self, Model, DEFAULT_MODEL, MODEL_HELP};
+use hpgl::{CommandReader, Event, Interpreter, Point};
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use structopt::StructOpt;
 
-fn draw_line(start: Point, end: Point, color: u8, orientation: Orientation, max_y: i32) {
-    let (x1, y1, x2, y2) = match orientation {
-        Orientation::Portrait => (start.y, start.x, end.y, end.x),
-        Orientation::Landscape => (start.x, max_y-start.y, end.x, max_y-end.y),
+fn draw_line(start: Point, end: Point, color: u8, swap_axes: bool, max_y: i32) {
+    let (x1, y1, x2, y2) = if swap_axes {
+        (start.y, start.x, end.y, end.x)
+    } else {
+        (start.x, max_y-start.y, end.x, max_y-end.y)
     };
     println!(
         "<line x1='{}' y1='{}' x2='{}' y2='{}' style='stroke:{};stroke-width:10'/>",
         x1, y1, x2, y2,
-        &["", "black", "red", "blue", "green", "yellow", "orange", "brown", "pink"][color as usize],
+        // models can have more pens than there are colors here, so the rest are drawn in black
+        ["", "black", "red", "blue", "green", "yellow", "orange", "brown", "pink"]
+            .get(color as usize)
+            .unwrap_or(&"black"),
     );
 }
 
 #[derive(Debug, StructOpt)]
 struct Args {
+    #[structopt(help = "HPGL file to visualize, or '-' to read from stdin")]
     file: PathBuf,
-    #[structopt(long, default_value="7440", help="Plotter model. Options: '7440', '7475a3', '7475a4'")]
+    #[structopt(long, default_value=DEFAULT_MODEL, help=MODEL_HELP)]
     model: Model,
     #[structopt(long, default_value="portrait", help="Display orientation. Options: 'portrait', 'landscape'")]
     orientation: Orientation,
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Model {
-    Hp7440,
-    Hp7475A3,
-    Hp7475A4,
-}
-
-impl FromStr for Model {
-    type Err = String;
-    fn from_str(model: &str) -> Result<Self, Self::Err> {
-        match model {
-            "7440" => Ok(Model::Hp7440),
-            "7475a3" => Ok(Model::Hp7475A3),
-            "7475a4" => Ok(Model::Hp7475A4),
-            _ => Err("Could not parse model".to_string()),
-        }
-    }
-}
-
 #[derive(Copy, Clone, Debug)]
 enum Orientation {
     Portrait,
@@ -79,65 +65,58 @@ impl FromStr for Orientation {
 fn main() -> std::io::Result<()> {
     let args = Args::from_args();
 
-    let hpgl_file = fs::read_to_string(args.file)?;
-
-    let commands = parse_commands(hpgl_file).unwrap();
+    let commands = CommandReader::open(&args.file)?;
+    let mut had_errors = false;
 
-    let mut color: u8 = 0;
-    let mut position: Point = Point { x: 0, y: 0 };
-    let mut pen_down: bool = false;
+    let mut interpreter = Interpreter::new();
+    interpreter.set_expand_line_types(true);
+    interpreter.set_expand_labels(true);
+    interpreter.set_hard_clip_limits(Some(args.model.hard_clip_limits));
 
-    let (x, y) = match args.model {
-        Model::Hp7440 => (7650, 10300),
-        Model::Hp7475A3 => (10365, 16640),
-        Model::Hp7475A4 => (7962, 10365),
-    };
-    match args.orientation {
-        Orientation::Portrait => println!("<html><body><svg viewBox='0 0 {} {}'>", x, y),
-        Orientation::Landscape => println!("<html><body><svg viewBox='0 0 {} {}'>", y, x),
+    let (_, Point { x, y }) = args.model.hard_clip_limits;
+    // the long edge of the paper is vertical in portrait, so the axes are swapped when the
+    // plotter's x axis runs along the long edge, and the other way around
+    let swap_axes = match args.orientation {
+        Orientation::Portrait => args.model.orientation == device::Orientation::Landscape,
+        Orientation::Landscape => args.model.orientation == device::Orientation::Portrait,
     };
+    if swap_axes {
+        println!("<html><body><svg viewBox='0 0 {} {}'>", y, x);
+    } else {
+        println!("<html><body><svg viewBox='0 0 {} {}'>", x, y);
+    }
 
     for cmd in commands {
-        match cmd {
-            Command::PenUp(points) => {
-                pen_down = false;
-                position = *points.last().unwrap_or(&position);
+        let cmd = match cmd {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e.diagnostic(&args.file));
+                had_errors = true;
+                continue;
             }
-            Command::PenDown(points) => {
-                for p in points {
-                    if pen_down && color != 0 {
-                        draw_line(position, p, color.clone(), args.orientation, x);
-                    }
-                    position = p;
-                    pen_down = true;
-                }
-                pen_down = true;
+        };
+        let events = match interpreter.execute(cmd) {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                had_errors = true;
+                break;
             }
-            Command::PlotAbsolute(points) => {
-                for p in points {
-                    if pen_down && color != 0 {
-                        draw_line(position, p, color.clone(), args.orientation, x);
-                    }
-                    position = p;
+        };
+        for event in events {
+            match event {
+                Event::Stroke { pen, from, to } if pen != 0 => {
+                    draw_line(from, to, pen, swap_axes, y);
                 }
+                // everything else doesn't draw anything that viz knows how to show yet
+                _ => {}
             }
-            Command::PlotRelative(points) => {
-                for p in points {
-                    let old_pos = position;
-                    position.x += p.x;
-                    position.y += p.y;
-                    if pen_down && color != 0 {
-                        draw_line(old_pos, position, color.clone(), args.orientation, x);
-                    }
-                }
-            }
-            Command::SelectPen(c) => {
-                color = c;
-            }
-            Command::Initialize => {}
         }
     }
     println!("</svg></body></html>");
 
+    if had_errors {
+        std::process::exit(1);
+    }
     Ok(())
 }